@@ -1,24 +1,56 @@
 //! Provides function for creating ensembles of particles with Boltzmann distribution
-use std::f64::consts::PI;
-use std::cmp::PartialOrd;
-use material::Material;
+use material::{Material, MultiBand};
 use linal::Vec2;
-use rng::Rng;
+use rng::{RandomGenerator, Rng};
 use time::get_time;
 
+/// Samples `n` momentums from Boltzmann distribution, seeding generator from system time
 pub fn initial_condition<T: Material>(m: &T, temperature: f64, n: usize) -> Vec<Vec2> {
-    let seed = get_time().nsec as u32;
-    let mut rng = Rng::new(seed);
-    let mut points: Vec<Vec2> = Vec::with_capacity(n);
+    initial_condition_seeded(m, temperature, n, get_time().nsec as u32)
+}
+
+/// Samples `n` momentums from Boltzmann distribution. The same `seed` gives the same points.
+pub fn initial_condition_seeded<T: Material>(m: &T,
+                                             temperature: f64,
+                                             n: usize,
+                                             seed: u32)
+                                             -> Vec<Vec2> {
+    initial_condition_with(m, temperature, n, &mut Rng::new(seed))
+}
+
+/// Samples `n` momentums from Boltzmann distribution using given random generator
+pub fn initial_condition_with<T: Material, R: RandomGenerator>(m: &T,
+                                                               temperature: f64,
+                                                               n: usize,
+                                                               rng: &mut R)
+                                                               -> Vec<Vec2> {
+    initial_states_with(m, temperature, n, rng).into_iter().map(|(_, p)| p).collect()
+}
 
+/// Samples `n` pairs of band and momentum from Boltzmann distribution over all bands
+/// of material. For material with one band random numbers are drawn exactly as
+/// in `initial_condition_with`.
+pub fn initial_states_with<T: MultiBand, R: RandomGenerator>(m: &T,
+                                                             temperature: f64,
+                                                             n: usize,
+                                                             rng: &mut R)
+                                                             -> Vec<(usize, Vec2)> {
+    let mut points: Vec<(usize, Vec2)> = Vec::with_capacity(n);
+
+    let bands = m.bands();
+    let min_energy = (0..bands).map(|b| m.band_min_energy(b)).fold(f64::INFINITY, f64::min);
     let mut k = n;
-    let bz = m.brillouin_zone();
-    let (a, b) = bz.basis;
+    let bz = m.zone();
     while k > 0 {
-        let p = bz.a + a * rng.uniform() + b * rng.uniform();
+        let band = if bands > 1 {
+            ((rng.uniform() * bands as f64) as usize).min(bands - 1)
+        } else {
+            0
+        };
+        let p = bz.sample(rng.uniform(), rng.uniform());
 
-        if (rng.uniform() < ((m.min_energy() - m.energy(p)) / temperature).exp()) {
-            points.push(p);
+        if rng.uniform() < ((min_energy - m.band_energy(band, p)) / temperature).exp() {
+            points.push((band, p));
             k -= 1;
         }
     }   
@@ -29,92 +61,60 @@ pub fn initial_condition<T: Material>(m: &T, temperature: f64, n: usize) -> Vec<
 mod tests {
     use super::*;
     use material::*;
+    use materials::{Parabolic, Phonons};
     use stats::{Mean, MeanStd};
     
     extern crate linal;
     use linal::Vec2;
 
 
-    struct Parabolic {
-        mass: f64,
-        bz: BrillouinZone,
-    }
-
-    impl Parabolic {
-        pub fn new() -> Parabolic {
-            let bz = BrillouinZone::new(Vec2::new(-1, -1), Vec2::new(1, -1), Vec2::new(-1, 1));
-            Parabolic {
-                mass: 1.0,
-                bz: bz,
-            }
-        }
-    }
-
-    impl Material for Parabolic {
-        fn energy(&self, p: Vec2) -> f64 {
-            p.dot(p) / 2.0
-        }
-        /// Gradient of energy in momentum space
-        fn energy_gradient(&self, p: Vec2) -> Vec2 {
-            unimplemented!();
-        }
-
-        fn velocity(&self, p: Vec2) -> Vec2 {
-            unimplemented!();
-        }
-        /// Minimum of energy in brillouin zone
-        fn min_energy(&self) -> f64 {
-            0f64
-        }
-        /// Maximum of energy in brillouin zone
-        fn max_energy(&self) -> f64 {
-            1f64
-        }
-        /// Solves equation energy_polar(p, theta) = energy
-        fn momentums(&self, energy: f64, theta: f64) -> Vec<Vec2> {
-            unimplemented!();
-        }
-        /// brillouin zone structure
-        fn brillouin_zone(&self) -> &BrillouinZone {
-            &self.bz
-        }
-        /// optical phonon energy
-        fn optical_energy(&self) -> f64 {
-            unimplemented!();
-        }
-        /// optical phonon scattering probability
-        fn optical_scattering(&self, p: Vec2) -> f64 {
-            unimplemented!();
-        }
-        /// acoustic phonon scattering probability
-        fn acoustic_scattering(&self, p: Vec2) -> f64 {
-            unimplemented!();
-        }
-    }
-
-    fn is_ok(real: Vec2, expectation: Vec2, delta: Vec2) -> bool {
-        (real.x - expectation.x).abs() < delta.x && (real.y - expectation.y).abs() < delta.y
+    fn parabolic() -> Parabolic {
+        let bz = BrillouinZone::new(Vec2::new(-1, -1), Vec2::new(1, -1), Vec2::new(-1, 1));
+        Parabolic::new(1.0, bz, Phonons::new(0.0, 0.0, 0.0))
     }
 
     #[test]
     fn test_average_momentum() {
-        let ref m = Parabolic::new();
+        let m = &parabolic();
         let temperature = 0.05;
-        let ic = initial_condition(m, temperature, 1000000usize);
+        let ic = initial_condition_seeded(m, temperature, 1000000usize, 3);
         let average = ic.mean();
         let std = ic.mean_std();
-        assert!(average.x.abs() < std.x, format!("{} > {}", average.x.abs(), std.x));
-        assert!(average.y.abs() < std.y, format!("{} > {}", average.y.abs(), std.y));
+        assert!(average.x.abs() < std.x, "{} > {}", average.x.abs(), std.x);
+        assert!(average.y.abs() < std.y, "{} > {}", average.y.abs(), std.y);
     }
 
     #[test]
     fn test_average_energy() {
-        let ref m = Parabolic::new();
+        let m = &parabolic();
+        let temperature = 0.05;
+        let ic = initial_condition_seeded(m, temperature, 1000000usize, 3);
+        let energies: Vec<f64> = ic.iter().map(|&x| m.energy(x)).collect();
+        let average = energies.mean();
+        let std = energies.mean_std();
+        assert!((average - temperature).abs() < std * 2.0, "{} > {}", (average - temperature).abs(), 2.0*std);
+    }
+
+    #[test]
+    fn test_seeded_reproducible() {
+        let m = &parabolic();
+        let temperature = 0.05;
+        let a = initial_condition_seeded(m, temperature, 1000usize, 12345);
+        let b = initial_condition_seeded(m, temperature, 1000usize, 12345);
+        let c = initial_condition_seeded(m, temperature, 1000usize, 54321);
+        assert_eq!(a, b);
+        assert!(a != c);
+    }
+
+    #[test]
+    fn test_philox_average_energy() {
+        use rng::Philox;
+        let m = &parabolic();
         let temperature = 0.05;
-        let ic = initial_condition(m, temperature, 1000000usize);
+        let ic = initial_condition_with(m, temperature, 100000usize, &mut Philox::new(2017));
         let energies: Vec<f64> = ic.iter().map(|&x| m.energy(x)).collect();
         let average = energies.mean();
         let std = energies.mean_std();
-        assert!((average - temperature).abs() < std * 2.0, format!("{} > {}", (average - temperature).abs(), 2.0*std));
+        assert!((average - temperature).abs() < std * 3.0, "{} > {}", (average - temperature).abs(), 3.0*std);
     }
 }
@@ -0,0 +1,29 @@
+//! Errors of reading materials and fields from files
+use std::fmt;
+use std::io;
+
+#[derive(Debug)]
+/// Error of reading material or field from file
+pub enum LoadError {
+    Io(io::Error),
+    /// malformed text
+    Parse(String),
+    /// data is well-formed, but does not describe valid object
+    Invalid(String),
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LoadError::Io(ref e) => write!(f, "{}", e),
+            LoadError::Parse(ref s) => write!(f, "{}", s),
+            LoadError::Invalid(ref s) => write!(f, "{}", s),
+        }
+    }
+}
+
+impl From<io::Error> for LoadError {
+    fn from(e: io::Error) -> LoadError {
+        LoadError::Io(e)
+    }
+}
@@ -1,10 +1,100 @@
+use std::f64::consts::PI;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
 use linal::Vec2;
+use error::LoadError;
+
+/// Time-dependent electromagnetic field acting on electron
+pub trait FieldSource: Sync {
+    /// Electric field at time `t`
+    fn electric(&self, t: f64) -> Vec2;
+    /// Z-component of magnetic field at time `t`
+    fn magnetic(&self, t: f64) -> f64;
+    /// Frequency, phase of which is recorded in histograms of scattering events,
+    /// 0 if field has no such frequency
+    fn frequency(&self) -> f64 {
+        0.0
+    }
+    /// Combination $n\_1 \omega\_1 + n\_2 \omega\_2$ of the first two frequencies of field,
+    /// missing ones are 0; by default $\omega\_1$ is `frequency` and there is no $\omega\_2$
+    fn combination(&self, n1: i32, _n2: i32) -> f64 {
+        n1 as f64 * self.frequency()
+    }
+    /// Force acting on electron with velocity `v` at time `t`
+    fn force(&self, v: Vec2, t: f64) -> Vec2 {
+        -(self.electric(t) + v.cross() * self.magnetic(t))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+/// Direction of rotation of circularly polarized field
+pub enum Rotation {
+    Counterclockwise,
+    Clockwise,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+/// Complex amplitude (Jones vector) $\mathcal{E} = E\' + i E\'\'$ of electric field wave
+/// $E(t) = \Re \mathcal{E} e\^{i (\omega t + \varphi)}
+/// = E\' \cos(\omega t + \varphi) - E\'\' \sin(\omega t + \varphi)$
+pub struct Jones {
+    pub re: Vec2,
+    pub im: Vec2,
+}
+
+impl Jones {
+    pub fn new(re: Vec2, im: Vec2) -> Jones {
+        Jones { re, im }
+    }
+
+    /// Linearly polarized wave with amplitude `e`
+    pub fn linear(e: Vec2) -> Jones {
+        Jones::new(e, Vec2::zero())
+    }
+
+    /// Elliptically polarized wave with major semiaxis `amplitude` directed at angle `angle`
+    /// to x axis and ratio of minor and major semiaxes $|\epsilon|$. Field rotates counterclockwise
+    /// for positive `ellipticity` $\epsilon$ and clockwise for negative one, $\epsilon = 0$ gives
+    /// linear polarization and $\epsilon = \pm 1$ gives circular one.
+    pub fn elliptic(amplitude: f64, angle: f64, ellipticity: f64) -> Jones {
+        let axis = Vec2::from_polar(amplitude, angle);
+        Jones::new(axis, axis.cross() * ellipticity)
+    }
+
+    /// Circularly polarized wave, which rotates in given direction
+    pub fn circular(amplitude: f64, rotation: Rotation) -> Jones {
+        let ellipticity = match rotation {
+            Rotation::Counterclockwise => 1.0,
+            Rotation::Clockwise => -1.0,
+        };
+        Jones::elliptic(amplitude, 0.0, ellipticity)
+    }
+
+    /// Wave with opposite direction of rotation
+    pub fn reversed(&self) -> Jones {
+        Jones::new(self.re, -self.im)
+    }
+
+    /// Field at given phase $\omega t + \varphi$
+    pub fn at(&self, phase: f64) -> Vec2 {
+        let (sin, cos) = phase.sin_cos();
+        self.re * cos - self.im * sin
+    }
+}
+
+impl From<Vec2> for Jones {
+    fn from(e: Vec2) -> Jones {
+        Jones::linear(e)
+    }
+}
 
 #[derive(Clone)]
 /// Electromagnetic fields
 pub struct Fields {
-    /// Amplitudes of constant, first and second wave electric fields
-    pub e: (Vec2, Vec2, Vec2),
+    /// Amplitudes of constant, first and second wave electric fields, for waves they are
+    /// Jones vectors
+    pub e: (Vec2, Jones, Jones),
     /// Amplitudes of z-component constant, first and second wave magnetic fields
     pub b: (f64, f64, f64),
     /// Frequences of waves. First value is unused and exists for consistency
@@ -13,9 +103,14 @@ pub struct Fields {
 }
 
 impl Fields {
-    pub fn new(e: (Vec2, Vec2, Vec2), b: (f64, f64, f64), omega: (f64, f64), phi: f64) -> Fields {
+    /// Creates fields, amplitudes of waves are `Vec2` for linear polarization or `Jones`
+    #[allow(clippy::redundant_field_names)]
+    pub fn new<A, B>(e: (Vec2, A, B), b: (f64, f64, f64), omega: (f64, f64), phi: f64) -> Fields
+        where A: Into<Jones>,
+              B: Into<Jones>
+    {
         Fields {
-            e: e,
+            e: (e.0, e.1.into(), e.2.into()),
             b: b,
             omega: (0.0, omega.0, omega.1),
             phi: phi,
@@ -28,3 +123,421 @@ impl Fields {
                     0.0)
     }
 }
+
+impl FieldSource for Fields {
+    fn electric(&self, t: f64) -> Vec2 {
+        self.e.0 + self.e.1.at(self.omega.1 * t) + self.e.2.at(self.omega.2 * t + self.phi)
+    }
+    fn magnetic(&self, t: f64) -> f64 {
+        self.b.0 + self.b.1 * (self.omega.1 * t).cos() + self.b.2 * (self.omega.2 * t + self.phi).cos()
+    }
+    fn frequency(&self) -> f64 {
+        self.omega.1
+    }
+    fn combination(&self, n1: i32, n2: i32) -> f64 {
+        n1 as f64 * self.omega.1 + n2 as f64 * self.omega.2
+    }
+}
+
+#[derive(Clone)]
+/// Wave $\Re \mathcal{E} e\^{i (\omega t + \varphi)}$, $B \cos(\omega t + \varphi)$
+pub struct Harmonic {
+    pub e: Jones,
+    pub b: f64,
+    pub omega: f64,
+    pub phase: f64,
+}
+
+impl Harmonic {
+    /// Creates harmonic, amplitude `e` is `Vec2` for linear polarization or `Jones`
+    pub fn new<J: Into<Jones>>(e: J, b: f64, omega: f64, phase: f64) -> Harmonic {
+        Harmonic {
+            e: e.into(),
+            b,
+            omega,
+            phase,
+        }
+    }
+}
+
+#[derive(Clone)]
+/// Constant fields and any number of harmonics, phase of the first harmonic is recorded
+/// in histograms
+pub struct Harmonics {
+    pub e: Vec2,
+    pub b: f64,
+    pub harmonics: Vec<Harmonic>,
+}
+
+impl Harmonics {
+    pub fn new(e: Vec2, b: f64, harmonics: Vec<Harmonic>) -> Harmonics {
+        Harmonics { e, b, harmonics }
+    }
+}
+
+impl FieldSource for Harmonics {
+    fn electric(&self, t: f64) -> Vec2 {
+        self.harmonics.iter().fold(self.e, |e, h| e + h.e.at(h.omega * t + h.phase))
+    }
+    fn magnetic(&self, t: f64) -> f64 {
+        self.harmonics.iter().fold(self.b, |b, h| b + h.b * (h.omega * t + h.phase).cos())
+    }
+    fn frequency(&self) -> f64 {
+        self.harmonics.first().map_or(0.0, |h| h.omega)
+    }
+    fn combination(&self, n1: i32, n2: i32) -> f64 {
+        let omega = |k: usize| self.harmonics.get(k).map_or(0.0, |h| h.omega);
+        n1 as f64 * omega(0) + n2 as f64 * omega(1)
+    }
+}
+
+#[derive(Clone)]
+/// Pulse $E \exp(-(t - t\_0)\^2 / 2 \tau\^2) \cos(\omega (t - t\_0) + \varphi)$
+/// and the same one of magnetic field
+pub struct GaussianPulse {
+    pub e: Vec2,
+    pub b: f64,
+    /// time of maximum $t\_0$
+    pub center: f64,
+    /// duration $\tau$
+    pub width: f64,
+    /// carrier frequency $\omega$
+    pub omega: f64,
+    pub phase: f64,
+}
+
+impl GaussianPulse {
+    pub fn new(e: Vec2, b: f64, center: f64, width: f64, omega: f64, phase: f64) -> GaussianPulse {
+        GaussianPulse {
+            e,
+            b,
+            center,
+            width,
+            omega,
+            phase,
+        }
+    }
+
+    fn envelope(&self, t: f64) -> f64 {
+        let x = (t - self.center) / self.width;
+        (-x * x / 2.0).exp() * (self.omega * (t - self.center) + self.phase).cos()
+    }
+}
+
+impl FieldSource for GaussianPulse {
+    fn electric(&self, t: f64) -> Vec2 {
+        self.e * self.envelope(t)
+    }
+    fn magnetic(&self, t: f64) -> f64 {
+        self.b * self.envelope(t)
+    }
+    fn frequency(&self) -> f64 {
+        self.omega
+    }
+}
+
+#[derive(Clone)]
+/// Square wave, which equals $E$ during first `duty` fraction of period $2 \pi / \omega$
+/// and $-E$ during the rest
+pub struct SquareWave {
+    pub e: Vec2,
+    pub b: f64,
+    pub omega: f64,
+    pub phase: f64,
+    pub duty: f64,
+}
+
+impl SquareWave {
+    pub fn new(e: Vec2, b: f64, omega: f64, phase: f64) -> SquareWave {
+        SquareWave {
+            e,
+            b,
+            omega,
+            phase,
+            duty: 0.5,
+        }
+    }
+
+    fn sign(&self, t: f64) -> f64 {
+        let x = ((self.omega * t + self.phase) / (2.0 * PI)).rem_euclid(1.0);
+        if x < self.duty { 1.0 } else { -1.0 }
+    }
+}
+
+impl FieldSource for SquareWave {
+    fn electric(&self, t: f64) -> Vec2 {
+        self.e * self.sign(t)
+    }
+    fn magnetic(&self, t: f64) -> f64 {
+        self.b * self.sign(t)
+    }
+    fn frequency(&self) -> f64 {
+        self.omega
+    }
+}
+
+#[derive(Clone)]
+/// Linear chirp $E \cos(\omega t + \alpha t\^2 / 2 + \varphi)$, instantaneous frequency
+/// $\omega + \alpha t$ changes with `rate` $\alpha$. Chirp has no constant frequency, so
+/// `frequency` is 0 and phase of scattering events is not recorded.
+pub struct Chirp {
+    pub e: Vec2,
+    pub b: f64,
+    /// initial frequency $\omega$
+    pub omega: f64,
+    pub rate: f64,
+    pub phase: f64,
+}
+
+impl Chirp {
+    pub fn new(e: Vec2, b: f64, omega: f64, rate: f64, phase: f64) -> Chirp {
+        Chirp {
+            e,
+            b,
+            omega,
+            rate,
+            phase,
+        }
+    }
+
+    fn wave(&self, t: f64) -> f64 {
+        (self.omega * t + self.rate * t * t / 2.0 + self.phase).cos()
+    }
+}
+
+impl FieldSource for Chirp {
+    fn electric(&self, t: f64) -> Vec2 {
+        self.e * self.wave(t)
+    }
+    fn magnetic(&self, t: f64) -> f64 {
+        self.b * self.wave(t)
+    }
+}
+
+#[derive(Clone)]
+/// Fields sampled at increasing times and linearly interpolated between them.
+/// Before the first and after the last sample fields are constant.
+///
+/// Samples are private, so they are checked by `Sampled::new`.
+pub struct Sampled {
+    times: Vec<f64>,
+    e: Vec<Vec2>,
+    b: Vec<f64>,
+}
+
+impl Sampled {
+    /// Creates field from samples, there should be at least one of them, times should increase,
+    /// all vectors should have the same length and all values should be finite
+    pub fn new(times: Vec<f64>, e: Vec<Vec2>, b: Vec<f64>) -> Result<Sampled, LoadError> {
+        if times.is_empty() {
+            return Err(LoadError::Invalid("no samples".to_string()));
+        }
+        if e.len() != times.len() || b.len() != times.len() {
+            return Err(LoadError::Invalid(format!("{} times, {} electric and {} magnetic fields",
+                                                  times.len(),
+                                                  e.len(),
+                                                  b.len())));
+        }
+        if times.iter().any(|t| !t.is_finite()) {
+            return Err(LoadError::Invalid("times are not finite".to_string()));
+        }
+        let finite = e.iter().all(|e| e.x.is_finite() && e.y.is_finite());
+        if !finite || b.iter().any(|b| !b.is_finite()) {
+            return Err(LoadError::Invalid("fields are not finite".to_string()));
+        }
+        if let Some(i) = (1..times.len()).find(|&i| times[i] <= times[i - 1]) {
+            return Err(LoadError::Invalid(format!("time is not increasing at sample {}", i)));
+        }
+        Ok(Sampled { times, e, b })
+    }
+
+    /// Reads samples from file, see `parse`
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Sampled, LoadError> {
+        let mut s = String::new();
+        File::open(path)?.read_to_string(&mut s)?;
+        Sampled::parse(&s)
+    }
+
+    /// Parses samples from text. Every line contains time, components of electric field and
+    /// magnetic field separated by whitespace. Everything after `#` till the end of line
+    /// is ignored.
+    pub fn parse(s: &str) -> Result<Sampled, LoadError> {
+        let (mut times, mut e, mut b) = (Vec::new(), Vec::new(), Vec::new());
+        for (i, line) in s.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("");
+            if line.trim().is_empty() {
+                continue;
+            }
+            let values = line.split_whitespace()
+                .map(|x| x.parse::<f64>())
+                .collect::<Result<Vec<f64>, _>>()
+                .map_err(|_| LoadError::Parse(format!("invalid number at line {}", i + 1)))?;
+            if values.len() != 4 {
+                return Err(LoadError::Parse(format!("4 values expected at line {}", i + 1)));
+            }
+            if times.last().is_some_and(|&t| values[0] <= t) {
+                return Err(LoadError::Parse(format!("time is not increasing at line {}", i + 1)));
+            }
+            times.push(values[0]);
+            e.push(Vec2::new(values[1], values[2]));
+            b.push(values[3]);
+        }
+        if times.is_empty() {
+            return Err(LoadError::Parse("no samples".to_string()));
+        }
+        Sampled::new(times, e, b)
+    }
+
+    /// Times of samples
+    pub fn times(&self) -> &[f64] {
+        &self.times
+    }
+
+    /// Sampled electric fields
+    pub fn e(&self) -> &[Vec2] {
+        &self.e
+    }
+
+    /// Sampled magnetic fields
+    pub fn b(&self) -> &[f64] {
+        &self.b
+    }
+
+    /// Index of sample before `t` and weight of the next one
+    fn locate(&self, t: f64) -> (usize, f64) {
+        let n = self.times.len();
+        if t <= self.times[0] {
+            return (0, 0.0);
+        }
+        if t >= self.times[n - 1] {
+            return (n - 1, 0.0);
+        }
+        let i = self.times.partition_point(|&x| x <= t) - 1;
+        (i, (t - self.times[i]) / (self.times[i + 1] - self.times[i]))
+    }
+}
+
+impl FieldSource for Sampled {
+    fn electric(&self, t: f64) -> Vec2 {
+        let (i, w) = self.locate(t);
+        if w == 0.0 { self.e[i] } else { self.e[i] * (1.0 - w) + self.e[i + 1] * w }
+    }
+    fn magnetic(&self, t: f64) -> f64 {
+        let (i, w) = self.locate(t);
+        if w == 0.0 { self.b[i] } else { self.b[i] * (1.0 - w) + self.b[i + 1] * w }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_harmonics() {
+        let fields = Fields::new((Vec2::new(0.1, 0.0), Vec2::new(0.0, 0.2), Vec2::new(0.3, 0.1)),
+                                 (1.0, 0.5, 0.2),
+                                 (2.0, 3.0),
+                                 0.7);
+        let harmonics = Harmonics::new(Vec2::new(0.1, 0.0),
+                                       1.0,
+                                       vec![Harmonic::new(Vec2::new(0.0, 0.2), 0.5, 2.0, 0.0),
+                                            Harmonic::new(Vec2::new(0.3, 0.1), 0.2, 3.0, 0.7)]);
+        for &t in &[0.0, 0.4, 1.3, 7.5] {
+            assert!((fields.electric(t) - harmonics.electric(t)).len() < 1e-12);
+            assert!((fields.magnetic(t) - harmonics.magnetic(t)).abs() < 1e-12);
+        }
+        assert_eq!(harmonics.frequency(), fields.frequency());
+        assert_eq!(harmonics.combination(-2, 1), fields.combination(-2, 1));
+        assert_eq!(fields.combination(-2, 1), -1.0);
+        // sources with one frequency have no second one
+        let pulse = GaussianPulse::new(Vec2::new(1.0, 0.0), 0.0, 5.0, 1.0, 2.0, 0.0);
+        assert_eq!(pulse.combination(3, 1), 6.0);
+        let v = Vec2::new(0.3, -0.4);
+        let force = -(fields.electric(1.3) + v.cross() * fields.magnetic(1.3));
+        assert!((fields.force(v, 1.3) - force).len() < 1e-12);
+    }
+
+    #[test]
+    fn test_polarization() {
+        let circular = Jones::circular(2.0, Rotation::Counterclockwise);
+        for i in 0..8 {
+            let phase = i as f64 * PI / 4.0;
+            // constant field rotates counterclockwise
+            assert!((circular.at(phase) - Vec2::from_polar(2.0, phase)).len() < 1e-12);
+            assert!((circular.reversed().at(phase) - Vec2::from_polar(2.0, -phase)).len() < 1e-12);
+        }
+        assert_eq!(Jones::circular(2.0, Rotation::Clockwise), circular.reversed());
+        let elliptic = Jones::elliptic(1.0, PI / 2.0, 0.5);
+        assert!((elliptic.at(0.0) - Vec2::new(0.0, 1.0)).len() < 1e-12);
+        assert!((elliptic.at(PI / 2.0) - Vec2::new(-0.5, 0.0)).len() < 1e-12);
+        assert_eq!(Jones::elliptic(1.0, 0.0, 0.0), Jones::linear(Vec2::new(1.0, 0.0)));
+
+        let fields = Fields::new((Vec2::zero(), circular, Vec2::new(0.0, 1.0)),
+                                 (0.0, 0.0, 0.0),
+                                 (1.0, 2.0),
+                                 0.0);
+        assert!((fields.electric(PI / 2.0) - Vec2::new(0.0, 1.0)).len() < 1e-12);
+        assert_eq!(fields.e.1, circular);
+        assert_eq!(fields.e.2, Jones::linear(Vec2::new(0.0, 1.0)));
+        let harmonics = Harmonics::new(Vec2::zero(),
+                                       0.0,
+                                       vec![Harmonic::new(circular, 0.0, 1.0, 0.0),
+                                            Harmonic::new(Vec2::new(0.0, 1.0), 0.0, 2.0, 0.0)]);
+        assert!((harmonics.electric(0.3) - fields.electric(0.3)).len() < 1e-12);
+    }
+
+    #[test]
+    fn test_waveforms() {
+        let e = Vec2::new(1.0, 2.0);
+        let pulse = GaussianPulse::new(e, 0.5, 10.0, 2.0, 0.0, 0.0);
+        assert_eq!(pulse.electric(10.0), e);
+        assert!((pulse.magnetic(12.0) - 0.5 * (-0.5f64).exp()).abs() < 1e-12);
+        assert!(pulse.electric(30.0).len() < 1e-20);
+
+        let square = SquareWave::new(e, 1.0, PI, 0.0);
+        assert_eq!(square.electric(0.2), e);
+        assert_eq!(square.electric(1.2), -e);
+        assert_eq!(square.magnetic(-0.5), -1.0);
+        assert_eq!(SquareWave { duty: 0.25, ..square }.magnetic(0.7), -1.0);
+
+        // phase $t + t\^2 / 4$ reaches $\psi$ at $t = 2 (\sqrt{1 + \psi} - 1)$,
+        // so half periods shorten with time
+        let chirp = Chirp::new(e, 0.0, 1.0, 0.5, 0.0);
+        let time = |psi: f64| 2.0 * ((1.0 + psi).sqrt() - 1.0);
+        assert_eq!(chirp.electric(0.0), e);
+        assert!(chirp.electric(time(PI / 2.0)).len() < 1e-12);
+        assert!((chirp.electric(time(PI)) + e).len() < 1e-12);
+        assert!((chirp.electric(time(2.0 * PI)) - e).len() < 1e-12);
+        assert!(time(2.0 * PI) - time(PI) < time(PI) - time(0.0));
+        assert_eq!(chirp.frequency(), 0.0);
+    }
+
+    #[test]
+    fn test_sampled() {
+        let sampled = Sampled::parse("# t ex ey bz\n0 0 0 1\n1 2 -2 3 # comment\n\n3 2 0 0\n")
+            .unwrap();
+        assert_eq!(sampled.times(), [0.0, 1.0, 3.0]);
+        assert_eq!(sampled.e()[1], Vec2::new(2.0, -2.0));
+        assert_eq!(sampled.b(), [1.0, 3.0, 0.0]);
+        assert_eq!(sampled.electric(-1.0), Vec2::zero());
+        assert!((sampled.electric(0.5) - Vec2::new(1.0, -1.0)).len() < 1e-12);
+        assert!((sampled.electric(2.0) - Vec2::new(2.0, -1.0)).len() < 1e-12);
+        assert_eq!(sampled.magnetic(0.25), 1.5);
+        assert_eq!(sampled.magnetic(5.0), 0.0);
+
+        assert!(Sampled::parse("").is_err());
+        assert!(Sampled::parse("0 1 2").is_err());
+        assert!(Sampled::parse("0 1 2 x").is_err());
+        assert!(Sampled::parse("1 0 0 0\n0 0 0 0").is_err());
+
+        assert!(Sampled::new(vec![], vec![], vec![]).is_err());
+        assert!(Sampled::new(vec![0.0, 1.0], vec![Vec2::zero(); 2], vec![0.0]).is_err());
+        assert!(Sampled::new(vec![0.0, 0.0], vec![Vec2::zero(); 2], vec![0.0; 2]).is_err());
+        assert!(Sampled::new(vec![0.0, f64::NAN], vec![Vec2::zero(); 2], vec![0.0; 2]).is_err());
+        let infinite = vec![Vec2::zero(), Vec2::new(0.0, f64::INFINITY)];
+        assert!(Sampled::new(vec![0.0, 1.0], infinite, vec![0.0; 2]).is_err());
+        assert!(Sampled::new(vec![0.0, 1.0], vec![Vec2::zero(); 2], vec![0.0, f64::NAN]).is_err());
+        assert!(Sampled::parse("0 0 0 nan").is_err());
+        assert!(Sampled::new(vec![0.0, 1.0], vec![Vec2::zero(); 2], vec![0.0; 2]).is_ok());
+    }
+}
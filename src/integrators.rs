@@ -0,0 +1,314 @@
+//! Integrators of equations of motion $\dot p = -E(t) - v(p) \times B(t)$ between scattering
+//! events
+use fields::FieldSource;
+use linal::Vec2;
+
+/// Method of integration of equations of motion
+pub trait Integrator: Sync {
+    /// Advances momentum `p` from time `t` to `t + dt` in field `f`, `velocity` gives
+    /// velocity of electron with given momentum
+    fn step(&self, p: Vec2, t: f64, dt: f64, f: &dyn FieldSource, velocity: &dyn Fn(Vec2) -> Vec2) -> Vec2;
+}
+
+fn runge<F>(p: Vec2, force: F, t: f64, dt: f64) -> Vec2
+    where F: Fn(Vec2, f64) -> Vec2
+{
+
+    let k1 = force(p, t);
+    let k2 = force(p + k1 * dt / 2.0, t + dt / 2.0);
+    let k3 = force(p + k2 * dt / 2.0, t + dt / 2.0);
+    let k4 = force(p + k3 * dt, t + dt);
+
+    p + (k1 + k2 * 2.0 + k3 * 2.0 + k4) * dt / 6.0
+}
+
+/// Classic Runge-Kutta method of 4th order with fixed step
+pub struct RungeKutta;
+
+impl Integrator for RungeKutta {
+    fn step(&self, p: Vec2, t: f64, dt: f64, f: &dyn FieldSource, velocity: &dyn Fn(Vec2) -> Vec2) -> Vec2 {
+        runge(p, |p, t| f.force(velocity(p), t), t, dt)
+    }
+}
+
+/// Coefficients of Dormand–Prince method
+const DP_C: [f64; 7] = [0.0, 1.0 / 5.0, 3.0 / 10.0, 4.0 / 5.0, 8.0 / 9.0, 1.0, 1.0];
+const DP_A: [[f64; 6]; 7] = [[0.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+                             [1.0 / 5.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+                             [3.0 / 40.0, 9.0 / 40.0, 0.0, 0.0, 0.0, 0.0],
+                             [44.0 / 45.0, -56.0 / 15.0, 32.0 / 9.0, 0.0, 0.0, 0.0],
+                             [19372.0 / 6561.0, -25360.0 / 2187.0, 64448.0 / 6561.0, -212.0 / 729.0,
+                              0.0, 0.0],
+                             [9017.0 / 3168.0, -355.0 / 33.0, 46732.0 / 5247.0, 49.0 / 176.0,
+                              -5103.0 / 18656.0, 0.0],
+                             [35.0 / 384.0, 0.0, 500.0 / 1113.0, 125.0 / 192.0, -2187.0 / 6784.0,
+                              11.0 / 84.0]];
+/// weights of 5th order solution
+const DP_B: [f64; 7] = [35.0 / 384.0, 0.0, 500.0 / 1113.0, 125.0 / 192.0, -2187.0 / 6784.0,
+                        11.0 / 84.0, 0.0];
+/// weights of embedded 4th order solution
+const DP_E: [f64; 7] = [5179.0 / 57600.0, 0.0, 7571.0 / 16695.0, 393.0 / 640.0,
+                        -92097.0 / 339200.0, 187.0 / 2100.0, 1.0 / 40.0];
+
+/// Dormand–Prince method of 5th order with adaptive step.
+///
+/// Step `dt` is divided into substeps, so that difference between solutions of 5th and 4th
+/// order on every substep is less than `tolerance` $\cdot \max(1, |p|)$.
+pub struct DormandPrince {
+    pub tolerance: f64,
+}
+
+impl DormandPrince {
+    pub fn new(tolerance: f64) -> DormandPrince {
+        DormandPrince { tolerance }
+    }
+
+    /// Makes one substep `h`, returns solution of 5th order and error estimate
+    fn substep<F>(&self, p: Vec2, force: F, t: f64, h: f64) -> (Vec2, f64)
+        where F: Fn(Vec2, f64) -> Vec2
+    {
+        let mut k = [Vec2::zero(); 7];
+        for i in 0..7 {
+            let mut q = p;
+            for j in 0..i {
+                q += k[j] * (DP_A[i][j] * h);
+            }
+            k[i] = force(q, t + DP_C[i] * h);
+        }
+        let mut high = p;
+        let mut low = p;
+        for i in 0..7 {
+            high += k[i] * (DP_B[i] * h);
+            low += k[i] * (DP_E[i] * h);
+        }
+        (high, (high - low).len())
+    }
+}
+
+impl Integrator for DormandPrince {
+    fn step(&self, p: Vec2, t: f64, dt: f64, f: &dyn FieldSource, velocity: &dyn Fn(Vec2) -> Vec2) -> Vec2 {
+        let force = |p: Vec2, t: f64| f.force(velocity(p), t);
+        let (mut p, mut t, mut h) = (p, t, dt);
+        let mut remaining = dt;
+        while remaining > 0.0 {
+            h = h.min(remaining);
+            let (next, error) = self.substep(p, force, t, h);
+            let tolerance = self.tolerance * p.len().max(1.0);
+            let factor = if error > 0.0 {
+                (0.9 * (tolerance / error).powf(0.2)).clamp(0.2, 5.0)
+            } else {
+                5.0
+            };
+            // слишком малый шаг принимаем, чтобы не зациклиться
+            if error <= tolerance || h < dt * 1e-9 {
+                p = next;
+                t += h;
+                remaining -= h;
+            }
+            h *= factor;
+        }
+        p
+    }
+}
+
+/// Relative tolerance of Newton iterations of magnetic rotation in Boris scheme
+const BORIS_TOLERANCE: f64 = 1e-14;
+/// Maximal number of Newton iterations of magnetic rotation in Boris scheme
+const BORIS_ITERATIONS: usize = 50;
+
+/// Boris scheme: two half steps of acceleration by electric field and rotation of momentum
+/// by magnetic field between them.
+///
+/// Rotation follows isoenergy contour: it is implicit midpoint rule
+/// $p\^+ = p\^- - v((p\^- + p\^+) / 2) \times B \Delta t$ with field at the middle of step,
+/// solved by Newton method. For parabolic bands, including anisotropic ones, energy is
+/// conserved exactly in pure magnetic field, and for isotropic bands it is rotation
+/// by angle $2 \arctan(\omega \Delta t / 2)$ of classic scheme.
+pub struct Boris;
+
+impl Integrator for Boris {
+    fn step(&self, p: Vec2, t: f64, dt: f64, f: &dyn FieldSource, velocity: &dyn Fn(Vec2) -> Vec2) -> Vec2 {
+        let middle = t + dt / 2.0;
+        let (e, b) = (f.electric(middle), f.magnetic(middle));
+        let p = p - e * (dt / 2.0);
+        let p = if b != 0.0 { rotate(p, b * dt, velocity) } else { p };
+        p - e * (dt / 2.0)
+    }
+}
+
+/// Solves $q = p - v((p + q) / 2) \times B \Delta t$ for `b_dt` $= B \Delta t$, starting from
+/// rotation with angular velocity $B (v \cdot p) / p\^2$
+fn rotate(p: Vec2, b_dt: f64, velocity: &dyn Fn(Vec2) -> Vec2) -> Vec2 {
+    let residual = |q: Vec2| q - p + velocity((p + q) / 2.0).cross() * b_dt;
+    let p2 = p.dot(p);
+    let mut q = if p2 > 0.0 {
+        let angle = 2.0 * (b_dt * velocity(p).dot(p) / p2 / 2.0).atan();
+        let (sin, cos) = angle.sin_cos();
+        Vec2::new(p.x * cos - p.y * sin, p.x * sin + p.y * cos)
+    } else {
+        p
+    };
+    // jacobian of residual is found by central differences
+    let h = 1e-7 * (1.0 + p.len());
+    let (dx, dy) = (Vec2::new(h, 0.0), Vec2::new(0.0, h));
+    for _ in 0..BORIS_ITERATIONS {
+        let g = residual(q);
+        let jx = (residual(q + dx) - residual(q - dx)) / (2.0 * h);
+        let jy = (residual(q + dy) - residual(q - dy)) / (2.0 * h);
+        let det = jx.x * jy.y - jy.x * jx.y;
+        if det == 0.0 {
+            break;
+        }
+        let delta = Vec2::new(g.x * jy.y - jy.x * g.y, jx.x * g.y - g.x * jx.y) / det;
+        q -= delta;
+        if delta.len() <= BORIS_TOLERANCE * (1.0 + q.len()) {
+            break;
+        }
+    }
+    q
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::PI;
+    use fields::Fields;
+
+    #[test]
+    #[allow(clippy::needless_borrows_for_generic_args)]
+    fn runge_circle() {
+        let f = |p: Vec2, _: f64| p.cross();
+        let dt = 0.01;
+        let mut p = Vec2::new(1.0, 0.0);
+        let mut t = 0.0;
+        while t < PI {
+            p = runge(p, &f, t, dt);
+            t += dt;
+        }
+        p = runge(p, &f, t, PI - t);
+        assert!((p - Vec2::new(-1.0, 0.0)).len() < 1e-8);
+    }
+
+    #[test]
+    #[allow(clippy::needless_borrows_for_generic_args)]
+    fn runge_parabola() {
+        let f = |_: Vec2, t: f64| Vec2::new(0.0, t);
+        let dt = 0.01;
+        let mut p = Vec2::new(1.0, 0.0);
+        let mut t = 0.0;
+        while t < 1.0 {
+            p = runge(p, &f, t, dt);
+            t += dt;
+        }
+        p = runge(p, &f, t, 1.0 - t);
+        assert!((p - Vec2::new(1.0, 0.5)).len() < 1e-8);
+    }
+
+    #[test]
+    #[allow(clippy::needless_borrows_for_generic_args)]
+    fn runge_sin() {
+        let f = |_: Vec2, t: f64| Vec2::new(0.0, t.sin());
+        let dt = 0.01;
+        let mut p = Vec2::new(1.0, 0.0);
+        let mut t = 0.0;
+        while t < PI {
+            p = runge(p, &f, t, dt);
+            t += dt;
+        }
+        p = runge(p, &f, t, PI - t);
+        assert!((p - Vec2::new(1.0, 2.0)).len() < 1e-8);
+    }
+
+    /// Magnetic field `b` and electric field `e` oscillating with frequency 1
+    fn fields(e: Vec2, b: f64) -> Fields {
+        Fields::new((Vec2::zero(), e, Vec2::zero()), (b, 0.0, 0.0), (1.0, 0.0), 0.0)
+    }
+
+    #[test]
+    fn dormand_prince_accuracy() {
+        // $p\_y = \sin t$ in one step
+        let f = &fields(Vec2::new(0.0, -1.0), 0.0);
+        let v = |p: Vec2| p;
+        let p = DormandPrince::new(1e-10).step(Vec2::new(1.0, 0.0), 0.0, PI / 2.0, f, &v);
+        assert!((p - Vec2::new(1.0, 1.0)).len() < 1e-8, "{:?}", p);
+        // cyclotron motion with $\omega\_c = B / m = 2$ in one step
+        let f = &fields(Vec2::zero(), 1.0);
+        let v = |p: Vec2| p * 2.0;
+        let p = DormandPrince::new(1e-10).step(Vec2::new(1.0, 0.0), 0.0, PI / 2.0, f, &v);
+        assert!((p - Vec2::new(-1.0, 0.0)).len() < 1e-8, "{:?}", p);
+    }
+
+    #[test]
+    fn boris_energy_conservation() {
+        let f = &fields(Vec2::zero(), 3.0);
+        let v = |p: Vec2| p * 0.5;
+        let (mut boris, mut rk) = (Vec2::new(1.0, 0.0), Vec2::new(1.0, 0.0));
+        let dt = 0.5;
+        for i in 0..1000 {
+            let t = i as f64 * dt;
+            boris = Boris.step(boris, t, dt, f, &v);
+            rk = RungeKutta.step(rk, t, dt, f, &v);
+        }
+        assert!((boris.len() - 1.0).abs() < 1e-12);
+        assert!((rk.len() - 1.0).abs() > 1e-3);
+        // rotation by $2 \arctan(\omega\_c \Delta t / 2)$ every step
+        let angle = 2.0 * (1.5f64 * dt / 2.0).atan();
+        let p = Boris.step(Vec2::new(1.0, 0.0), 0.0, dt, f, &v);
+        assert!((p - Vec2::new(angle.cos(), angle.sin())).len() < 1e-12);
+    }
+
+    #[test]
+    fn boris_anisotropic_energy() {
+        // energy $p\_x\^2 / 2 + p\_y\^2 / 8$ is conserved for any step
+        let f = &fields(Vec2::zero(), 3.0);
+        let v = |p: Vec2| Vec2::new(p.x, p.y / 4.0);
+        let energy = |p: Vec2| p.x * p.x / 2.0 + p.y * p.y / 8.0;
+        let mut p = Vec2::new(1.0, 0.5);
+        let e0 = energy(p);
+        for i in 0..1000 {
+            p = Boris.step(p, i as f64 * 0.5, 0.5, f, &v);
+        }
+        assert!((energy(p) - e0).abs() < 1e-12 * e0);
+    }
+
+    #[test]
+    fn boris_superlattice() {
+        use material::Material;
+        use materials::{Phonons, Superlattice2D};
+
+        // second order convergence to exact solution in cosine band
+        let m = Superlattice2D::new((1.0, 0.5), (1.0, 2.0), Phonons::new(0.2, 0.1, 0.1));
+        let f = &Fields::new((Vec2::new(0.2, 0.1), Vec2::zero(), Vec2::zero()),
+                             (1.5, 0.0, 0.0),
+                             (0.0, 0.0),
+                             0.0);
+        let v = |p: Vec2| m.velocity(p);
+        let exact = DormandPrince::new(1e-13);
+        let errors: Vec<f64> = [0.02, 0.01]
+            .iter()
+            .map(|&dt: &f64| {
+                let (mut boris, mut dp) = (Vec2::new(0.3, -0.2), Vec2::new(0.3, -0.2));
+                for i in 0..(5.0 / dt).round() as usize {
+                    let t = i as f64 * dt;
+                    boris = Boris.step(boris, t, dt, f, &v);
+                    dp = exact.step(dp, t, dt, f, &v);
+                }
+                (boris - dp).len()
+            })
+            .collect();
+        assert!(errors[1] < 1e-4, "{:?}", errors);
+        assert!((errors[0] / errors[1] - 4.0).abs() < 0.5, "{:?}", errors);
+    }
+
+    #[test]
+    fn boris_electric_field() {
+        // uniform acceleration is exact
+        let f = &Fields::new((Vec2::new(0.3, -0.1), Vec2::zero(), Vec2::zero()),
+                             (0.0, 0.0, 0.0),
+                             (0.0, 0.0),
+                             0.0);
+        let v = |p: Vec2| p;
+        let p = Boris.step(Vec2::new(1.0, 0.0), 0.0, 2.0, f, &v);
+        assert!((p - Vec2::new(0.4, 0.2)).len() < 1e-12);
+    }
+}
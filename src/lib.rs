@@ -2,34 +2,208 @@ extern crate linal;
 extern crate time;
 
 pub mod material;
+pub mod materials;
 pub mod boltzmann;
 pub mod particle;
 pub mod fields;
 pub mod stats;
 pub mod probability;
-mod rng;
+pub mod validation;
+pub mod scattering;
+pub mod integrators;
+pub mod rng;
+pub mod error;
+#[cfg(test)]
+mod test_support;
 
-pub use material::Material;
+pub use material::{Material, MultiBand};
 pub use stats::Stats;
-pub use fields::Fields;
+pub use fields::{Fields, FieldSource};
 pub use probability::probability;
-use particle::Particle;
-use rng::Rng;
-use boltzmann::initial_condition;
+pub use particle::RunOptions;
+use particle::{Particle, Summary, free_flight_rate};
+use rng::{RandomGenerator, Rng};
+use boltzmann::initial_states_with;
 use time::get_time;
 
-pub fn create_ensemble<T: Material>(n: usize,
-                                    m: &T,
-                                    temperature: f64)
-                                    -> Vec<Particle<T>> {
-    let mut rng = Rng::new(get_time().nsec as u32);
-    let init_condition = initial_condition(m, temperature, n);
+/// Creates ensemble of `n` particles with Boltzmann distribution, seeded from system time
+pub fn create_ensemble<T: MultiBand>(n: usize,
+                                     m: &T,
+                                     temperature: f64)
+                                     -> Vec<Particle<'_, T>> {
+    create_ensemble_seeded(n, m, temperature, get_time().nsec as u32)
+}
+
+/// Creates ensemble of `n` particles with Boltzmann distribution.
+///
+/// Initial conditions and seed of every particle are derived from master `seed`,
+/// so the same `seed` gives the same ensemble and the same results of `Particle::run`.
+pub fn create_ensemble_seeded<T: MultiBand>(n: usize,
+                                            m: &T,
+                                            temperature: f64,
+                                            seed: u32)
+                                            -> Vec<Particle<'_, T>> {
+    create_ensemble_with(n, m, temperature, &Rng::new(seed))
+}
+
+/// Creates ensemble of `n` particles with Boltzmann distribution.
+///
+/// Initial conditions (band and momentum) are drawn from stream 0 of `rng`,
+/// particle `i` gets stream `i + 1`.
+pub fn create_ensemble_with<'a, T: MultiBand, R: RandomGenerator>(n: usize,
+                                                                  m: &'a T,
+                                                                  temperature: f64,
+                                                                  rng: &R)
+                                                                  -> Vec<Particle<'a, T, R>> {
+    let init_condition = initial_states_with(m, temperature, n, &mut rng.stream(0));
 
     let mut ensemble = Vec::new();
 
-    for ic in init_condition {
-        ensemble.push(Particle::new(m, ic, rng.rand()));
+    for (i, (band, ic)) in init_condition.into_iter().enumerate() {
+        ensemble.push(Particle::with_band(m, band, ic, rng.stream(i as u32 + 1)));
     }
 
     ensemble
-}
\ No newline at end of file
+}
+
+/// Runs every particle of ensemble for time `all_time` with step `dt` on `options.threads`
+/// threads and returns summaries in particle order, see `Particle::run_with`.
+///
+/// Particles are split into contiguous chunks, one per thread, so result does not depend on
+/// number of threads. Probability of free flight is estimated once for material shared
+/// by particles.
+pub fn run_ensemble<T: MultiBand + Sync, R: RandomGenerator + Sync>(ensemble: &[Particle<T, R>],
+                                        dt: f64,
+                                        all_time: f64,
+                                        f: &dyn FieldSource,
+                                        options: &RunOptions)
+                                        -> Vec<Summary> {
+    use std::thread;
+
+    let threads = if options.threads == 0 {
+        thread::available_parallelism().map(|n| n.get()).unwrap_or(1)
+    } else {
+        options.threads
+    };
+    if ensemble.is_empty() {
+        return Vec::new();
+    }
+    let chunk_size = ensemble.len().div_ceil(threads);
+    let material = ensemble[0].material();
+    let rate = options.free_flight_rate.unwrap_or_else(|| free_flight_rate(material));
+    let shared = &RunOptions { free_flight_rate: Some(rate), ..options.clone() };
+    let options = |x: &Particle<T, R>| {
+        if options.free_flight_rate.is_some() || std::ptr::eq(x.material(), material) {
+            shared
+        } else {
+            options
+        }
+    };
+
+    thread::scope(|s| {
+        let handles: Vec<_> = ensemble.chunks(chunk_size)
+            .map(|chunk| {
+                s.spawn(move || {
+                    chunk.iter()
+                        .map(|x| x.run_with(dt, all_time, f, options(x)))
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+
+        let mut result = Vec::with_capacity(ensemble.len());
+        for handle in handles {
+            result.extend(handle.join().unwrap());
+        }
+        result
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use material::BrillouinZone;
+    use materials::{Parabolic, Phonons};
+    use linal::Vec2;
+
+    fn parabolic() -> Parabolic {
+        let bz = BrillouinZone::new(Vec2::new(-1.0, -1.0),
+                                    Vec2::new(1.0, -1.0),
+                                    Vec2::new(-1.0, 1.0));
+        Parabolic::new(10.0, bz, Phonons::new(5e-2, 3.0, 3.0))
+    }
+
+    fn threads(threads: usize) -> RunOptions<'static> {
+        RunOptions { threads, ..RunOptions::default() }
+    }
+
+    #[test]
+    fn test_run_ensemble_thread_independent() {
+        let m = &parabolic();
+        let f = &Fields::new((Vec2::new(0.1, 0.0), Vec2::zero(), Vec2::zero()),
+                             (0.0, 0.0, 0.0),
+                             (0.0, 0.0),
+                             0.0);
+        let ensemble = create_ensemble_seeded(37, m, 7e-3, 2017);
+        let one = Stats::from_ensemble(&run_ensemble(&ensemble, 1e-1, 20.0, f, &threads(1)));
+        let many = Stats::from_ensemble(&run_ensemble(&ensemble, 1e-1, 20.0, f, &threads(4)));
+        let serial: Vec<Summary> = ensemble.iter().map(|x| x.run(1e-1, 20.0, f)).collect();
+        let serial = Stats::from_ensemble(&serial);
+        assert_eq!(one.current, many.current);
+        assert_eq!(one.current_std, many.current_std);
+        assert_eq!(one.energy, many.energy);
+        assert_eq!(one.current, serial.current);
+        assert_eq!(one.acoustic, serial.acoustic);
+    }
+
+    #[test]
+    fn test_create_ensemble_with_philox() {
+        use rng::Philox;
+        let m = &parabolic();
+        let f = &Fields::zero();
+        let a = create_ensemble_with(16, m, 7e-3, &Philox::new(2017));
+        let b = create_ensemble_with(16, m, 7e-3, &Philox::new(2017));
+        let a = Stats::from_ensemble(&run_ensemble(&a, 1e-1, 20.0, f, &threads(3)));
+        let b = Stats::from_ensemble(&run_ensemble(&b, 1e-1, 20.0, f, &threads(2)));
+        assert_eq!(a.current, b.current);
+        assert_eq!(a.energy, b.energy);
+    }
+
+    #[test]
+    fn test_run_ensemble_with_integrator() {
+        use integrators::{Boris, DormandPrince};
+        let m = &parabolic();
+        let f = &Fields::new((Vec2::new(0.1, 0.0), Vec2::zero(), Vec2::zero()),
+                             (0.5, 0.0, 0.0),
+                             (0.0, 0.0),
+                             0.0);
+        let ensemble = create_ensemble_seeded(20, m, 7e-3, 2017);
+        let rk = &RunOptions::default();
+        let rk = Stats::from_ensemble(&run_ensemble(&ensemble, 1e-2, 5.0, f, rk));
+        let dp = &RunOptions { integrator: &DormandPrince::new(1e-10), ..RunOptions::default() };
+        let dp = Stats::from_ensemble(&run_ensemble(&ensemble, 1e-2, 5.0, f, dp));
+        let boris = &RunOptions { integrator: &Boris, ..RunOptions::default() };
+        let boris = Stats::from_ensemble(&run_ensemble(&ensemble, 1e-2, 5.0, f, boris));
+        assert!((rk.current - dp.current).len() < 1e-6);
+        assert!((rk.current - boris.current).len() < 1e-3 * rk.current.len());
+    }
+
+    #[test]
+    fn test_run_ensemble_field_source() {
+        use fields::{Harmonic, Harmonics};
+        let m = &parabolic();
+        let f = &Fields::new((Vec2::new(0.05, 0.0), Vec2::new(0.0, 0.1), Vec2::zero()),
+                             (0.0, 0.0, 0.0),
+                             (1.0, 0.0),
+                             0.0);
+        let harmonics = &Harmonics::new(Vec2::new(0.05, 0.0),
+                                        0.0,
+                                        vec![Harmonic::new(Vec2::new(0.0, 0.1), 0.0, 1.0, 0.0)]);
+        let ensemble = create_ensemble_seeded(10, m, 7e-3, 2017);
+        let options = &RunOptions::default();
+        let a = Stats::from_ensemble(&run_ensemble(&ensemble, 1e-1, 10.0, f, options));
+        let b = Stats::from_ensemble(&run_ensemble(&ensemble, 1e-1, 10.0, harmonics, options));
+        assert_eq!(a.current, b.current);
+        assert_eq!(a.field_phase_ac, b.field_phase_ac);
+    }
+}
@@ -1,4 +1,10 @@
 use linal::{Vec2};
+use scattering::{ScatteringMechanism, Intervalley, phonon_mechanisms};
+
+/// Step of numerical differentiation relative to size of brillouin zone
+const GRADIENT_STEP: f64 = 1e-6;
+/// Number of intervals, in which ray is split while searching isoenergy points
+const RAY_INTERVALS: usize = 64;
 
 pub trait Material {
     /// Energy spectrum of electrons
@@ -7,53 +13,298 @@ pub trait Material {
     fn energy_polar(&self, p: f64, theta: f64) -> f64 {
         self.energy(Vec2::from_polar(p, theta))
     }
-    /// Gradient of energy in momentum space
-    fn energy_gradient(&self, p: Vec2) -> Vec2;
-
-    fn velocity(&self, p: Vec2) -> Vec2;
+    /// Gradient of energy in momentum space.
+    /// By default is calculated by central finite differences.
+    fn energy_gradient(&self, p: Vec2) -> Vec2 {
+        let (b1, b2) = self.brillouin_zone().basis();
+        let h = GRADIENT_STEP * (b1.len() + b2.len());
+        let dx = Vec2::new(h, 0.0);
+        let dy = Vec2::new(0.0, h);
+        Vec2::new(self.energy(p + dx) - self.energy(p - dx),
+                  self.energy(p + dy) - self.energy(p - dy)) / (2.0 * h)
+    }
+    /// Velocity of electron, equals to gradient of energy by default
+    fn velocity(&self, p: Vec2) -> Vec2 {
+        self.energy_gradient(p)
+    }
     /// Minimum of energy in brillouin zone
     fn min_energy(&self) -> f64;
     /// Maximum of energy in brillouin zone
     fn max_energy(&self) -> f64;
-    /// Solves equation energy_polar(p, theta) = energy
-    fn momentums(&self, energy: f64, theta: f64) -> Vec<Vec2>;
+    /// Solves equation energy_polar(p, theta) = energy.
+    /// By default returns all roots on ray from 0 to `BrillouinZone::pmax(theta)`
+    /// in ascending order, see `ray_roots`.
+    fn momentums(&self, energy: f64, theta: f64) -> Vec<Vec2> {
+        let pmax = self.brillouin_zone().pmax(theta);
+        let n = Vec2::from_polar(1.0, theta);
+        ray_roots(|p| self.energy_polar(p, theta) - energy,
+                  |p| self.energy_gradient(n * p).dot(n),
+                  pmax,
+                  RAY_INTERVALS)
+            .into_iter()
+            .map(|p| Vec2::from_polar(p, theta))
+            .collect()
+    }
     /// brillouin zone structure
     fn brillouin_zone(&self) -> &BrillouinZone;
     /// optical phonon energy
     fn optical_energy(&self) -> f64;
     /// optical phonon scattering probability
     fn optical_scattering(&self, p: Vec2) -> f64;
+    /// optical phonon absorption probability, zero by default
+    fn optical_absorption(&self, _p: Vec2) -> f64 {
+        0.0
+    }
     /// acoustic phonon scattering probability
     fn acoustic_scattering(&self, p: Vec2) -> f64;
+    /// Scattering processes of electron, by default emission and absorption of optical
+    /// phonons and acoustic phonons
+    fn mechanisms(&self) -> Vec<Box<dyn ScatteringMechanism>> {
+        phonon_mechanisms()
+    }
+}
+
+/// Material with several bands in common brillouin zone. Phonon scattering may move
+/// electron from band `from` to band `to`. Bands may be grouped into valleys, transitions
+/// between valleys are due to separate intervalley phonons.
+///
+/// Every `Material` is a material with one band.
+pub trait MultiBand {
+    /// Number of bands
+    fn bands(&self) -> usize;
+    /// Energy spectrum of electrons in band
+    fn band_energy(&self, band: usize, p: Vec2) -> f64;
+    /// Gradient of energy in band, by default is calculated by central finite differences
+    fn band_energy_gradient(&self, band: usize, p: Vec2) -> Vec2 {
+        let (b1, b2) = self.zone().basis;
+        let h = GRADIENT_STEP * (b1.len() + b2.len());
+        let dx = Vec2::new(h, 0.0);
+        let dy = Vec2::new(0.0, h);
+        Vec2::new(self.band_energy(band, p + dx) - self.band_energy(band, p - dx),
+                  self.band_energy(band, p + dy) - self.band_energy(band, p - dy)) / (2.0 * h)
+    }
+    /// Velocity of electron in band, equals to gradient of energy by default
+    fn band_velocity(&self, band: usize, p: Vec2) -> Vec2 {
+        self.band_energy_gradient(band, p)
+    }
+    /// Minimum of energy of band
+    fn band_min_energy(&self, band: usize) -> f64;
+    /// Maximum of energy of band
+    fn band_max_energy(&self, band: usize) -> f64;
+    /// Solutions of `band_energy(band, p) = energy` on ray with angle `theta`,
+    /// by default all roots in ascending order
+    fn band_momentums(&self, band: usize, energy: f64, theta: f64) -> Vec<Vec2> {
+        let pmax = self.zone().pmax(theta);
+        let n = Vec2::from_polar(1.0, theta);
+        ray_roots(|p| self.band_energy(band, n * p) - energy,
+                  |p| self.band_energy_gradient(band, n * p).dot(n),
+                  pmax,
+                  RAY_INTERVALS)
+            .into_iter()
+            .map(|p| Vec2::from_polar(p, theta))
+            .collect()
+    }
+    /// Brillouin zone shared by all bands
+    fn zone(&self) -> &BrillouinZone;
+    /// Energy of optical phonon emitted in transition between bands
+    fn band_optical_energy(&self, from: usize, to: usize) -> f64;
+    /// Optical phonon scattering probability from band `from` to band `to`
+    fn band_optical_scattering(&self, from: usize, to: usize, p: Vec2) -> f64;
+    /// Optical phonon absorption probability from band `from` to band `to`, zero by default
+    fn band_optical_absorption(&self, _from: usize, _to: usize, _p: Vec2) -> f64 {
+        0.0
+    }
+    /// Acoustic phonon scattering probability from band `from` to band `to`
+    fn band_acoustic_scattering(&self, from: usize, to: usize, p: Vec2) -> f64;
+    /// Number of valleys
+    fn valleys(&self) -> usize {
+        1
+    }
+    /// Valley, which band belongs to. All bands are in valley 0 by default
+    fn valley(&self, _band: usize) -> usize {
+        0
+    }
+    /// Energy of phonon emitted in intervalley transition from band `from` to band `to`
+    fn intervalley_energy(&self, _from: usize, _to: usize) -> f64 {
+        0.0
+    }
+    /// Intervalley phonon scattering probability from band `from` to band `to`
+    fn intervalley_scattering(&self, _from: usize, _to: usize, _p: Vec2) -> f64 {
+        0.0
+    }
+    /// Scattering processes of electron in all bands, by default optical, acoustic and
+    /// intervalley phonons
+    fn band_mechanisms(&self) -> Vec<Box<dyn ScatteringMechanism>> {
+        let mut mechanisms = phonon_mechanisms();
+        mechanisms.push(Box::new(Intervalley));
+        mechanisms
+    }
+}
+
+impl<T: Material> MultiBand for T {
+    fn bands(&self) -> usize {
+        1
+    }
+    fn band_energy(&self, _band: usize, p: Vec2) -> f64 {
+        self.energy(p)
+    }
+    fn band_energy_gradient(&self, _band: usize, p: Vec2) -> Vec2 {
+        self.energy_gradient(p)
+    }
+    fn band_velocity(&self, _band: usize, p: Vec2) -> Vec2 {
+        self.velocity(p)
+    }
+    fn band_min_energy(&self, _band: usize) -> f64 {
+        self.min_energy()
+    }
+    fn band_max_energy(&self, _band: usize) -> f64 {
+        self.max_energy()
+    }
+    fn band_momentums(&self, _band: usize, energy: f64, theta: f64) -> Vec<Vec2> {
+        self.momentums(energy, theta)
+    }
+    fn zone(&self) -> &BrillouinZone {
+        self.brillouin_zone()
+    }
+    fn band_optical_energy(&self, _from: usize, _to: usize) -> f64 {
+        self.optical_energy()
+    }
+    fn band_optical_scattering(&self, _from: usize, _to: usize, p: Vec2) -> f64 {
+        self.optical_scattering(p)
+    }
+    fn band_optical_absorption(&self, _from: usize, _to: usize, p: Vec2) -> f64 {
+        self.optical_absorption(p)
+    }
+    fn band_acoustic_scattering(&self, _from: usize, _to: usize, p: Vec2) -> f64 {
+        self.acoustic_scattering(p)
+    }
+    fn band_mechanisms(&self) -> Vec<Box<dyn ScatteringMechanism>> {
+        self.mechanisms()
+    }
 }
 
 #[derive(Clone)]
-/// Representation of tetragonal brillouin zone of 2D material.
-/// a, b, c, and d are vertices of tetragon.
+/// Representation of brillouin zone of 2D material as convex polygon,
+/// which tiles momentum space under translations by reciprocal lattice vectors `basis`.
+///
+/// a, b, c, and d are vertices of parallelogram cell $a, a + b\_1, a + b\_1 + b\_2, a + b\_2$
+/// spanned by `basis`, which has the same area as zone. For zone created by
+/// `BrillouinZone::new` they are vertices of zone itself. Fields are private, so that
+/// they are consistent with vertices of polygon, and are read by accessors.
 pub struct BrillouinZone {
-    pub a: Vec2,
-    pub b: Vec2,
-    pub c: Vec2,
-    pub d: Vec2,
-    pub basis: (Vec2, Vec2),
-    pub dual_basis: (Vec2, Vec2),
+    a: Vec2,
+    b: Vec2,
+    c: Vec2,
+    d: Vec2,
+    /// Vertices of polygon in counterclockwise order
+    vertices: Vec<Vec2>,
+    /// Polygon coincides with parallelogram cell
+    is_cell: bool,
+    basis: (Vec2, Vec2),
+    dual_basis: (Vec2, Vec2),
 }
 
 impl BrillouinZone {
+    /// Parallelogram with vertices a, b, c and d, where $c = b + d - a$
     pub fn new(a: Vec2, b: Vec2, d: Vec2) -> BrillouinZone {
-        let v1 = b - a;
-        let v2 = d - a;
-        let c = a + v1 + v2;
-        let basis = (v1, v2);
-
-        BrillouinZone {
-            a: a,
-            b: b,
-            c: c,
-            d: d,
-            basis: basis,
+        let c = b + d - a;
+        let mut bz = BrillouinZone::polygon(vec![a, b, c, d], (b - a, d - a));
+        bz.set_cell(a);
+        bz.is_cell = true;
+        bz
+    }
+
+    /// Convex polygon with given vertices, which tiles plane with reciprocal lattice `basis`
+    pub fn polygon(vertices: Vec<Vec2>, basis: (Vec2, Vec2)) -> BrillouinZone {
+        let mut vertices = vertices;
+        let n = vertices.len();
+        let area: f64 = (0..n).map(|i| vertices[i].area(vertices[(i + 1) % n])).sum();
+        // `area` is positive for counterclockwise order
+        if area < 0.0 {
+            vertices.reverse();
+        }
+        let center = vertices.iter().fold(Vec2::zero(), |acc, &v| acc + v) / n as f64;
+        let mut bz = BrillouinZone {
+            a: Vec2::zero(),
+            b: Vec2::zero(),
+            c: Vec2::zero(),
+            d: Vec2::zero(),
+            is_cell: false,
+            vertices,
+            basis,
             dual_basis: Vec2::dual_basis(basis),
+        };
+        bz.set_cell(center - (basis.0 + basis.1) / 2.0);
+        bz
+    }
+
+    /// Sets vertices of parallelogram cell with corner `a`
+    fn set_cell(&mut self, a: Vec2) {
+        self.a = a;
+        self.b = a + self.basis.0;
+        self.c = a + self.basis.0 + self.basis.1;
+        self.d = a + self.basis.1;
+    }
+
+    /// Wigner–Seitz cell of reciprocal lattice with basis `b1`, `b2`
+    /// (hexagon for hexagonal lattice, rectangle for rectangular one)
+    pub fn wigner_seitz(b1: Vec2, b2: Vec2) -> BrillouinZone {
+        let size = 2.0 * (b1.len() + b2.len());
+        let mut vertices = vec![Vec2::new(-size, -size),
+                                Vec2::new(size, -size),
+                                Vec2::new(size, size),
+                                Vec2::new(-size, size)];
+        for n1 in -2i32..3 {
+            for n2 in -2i32..3 {
+                if n1 == 0 && n2 == 0 {
+                    continue;
+                }
+                let g = b1 * n1 as f64 + b2 * n2 as f64;
+                vertices = clip(&vertices, g, g.dot(g) / 2.0);
+            }
         }
+        // half-planes of far neighbours may touch vertices and produce duplicates
+        let eps = 1e-12 * size;
+        let mut unique: Vec<Vec2> = Vec::new();
+        for v in vertices {
+            if unique.last().map_or(true, |&u| (u - v).len() > eps) {
+                unique.push(v);
+            }
+        }
+        if unique.len() > 1 && (unique[0] - unique[unique.len() - 1]).len() <= eps {
+            unique.pop();
+        }
+        BrillouinZone::polygon(unique, (b1, b2))
+    }
+
+    /// Vertices of brillouin zone in counterclockwise order
+    pub fn vertices(&self) -> &[Vec2] {
+        &self.vertices
+    }
+
+    /// Vertices a, b, c and d of parallelogram cell
+    pub fn cell(&self) -> (Vec2, Vec2, Vec2, Vec2) {
+        (self.a, self.b, self.c, self.d)
+    }
+
+    /// Basis $b\_1, b\_2$ of reciprocal lattice
+    pub fn basis(&self) -> (Vec2, Vec2) {
+        self.basis
+    }
+
+    /// Basis dual to `basis`, $b\_i \cdot b\^j = \delta\_i\^j$
+    pub fn dual_basis(&self) -> (Vec2, Vec2) {
+        self.dual_basis
+    }
+
+    /// Checks if momentum lies in first brillouin zone (including its boundary)
+    pub fn contains(&self, p: Vec2) -> bool {
+        let n = self.vertices.len();
+        let scale = self.basis.0.len() + self.basis.1.len();
+        (0..n).all(|i| {
+            let (v1, v2) = (self.vertices[i], self.vertices[(i + 1) % n]);
+            (p - v1).area(v2 - v1) <= 1e-12 * scale * (v2 - v1).len()
+        })
     }
 
     /// Returns equivalent momentum in first brillouin zone
@@ -64,23 +315,60 @@ impl BrillouinZone {
         let n1 = pv.dot(self.dual_basis.0).floor();
         let n2 = pv.dot(self.dual_basis.1).floor();
 
-        p - self.basis.0 * n1 - self.basis.1 * n2
+        let p = p - self.basis.0 * n1 - self.basis.1 * n2;
+        if self.is_cell || self.contains(p) {
+            return p;
+        }
+
+        // polygon, which is not a parallelogram, is reached from parallelogram
+        // cell by translation to one of the neighbouring cells
+        let mut best = p;
+        let mut best_distance = f64::INFINITY;
+        for n1 in -2i32..3 {
+            for n2 in -2i32..3 {
+                let q = p - self.basis.0 * n1 as f64 - self.basis.1 * n2 as f64;
+                if self.contains(q) {
+                    return q;
+                }
+                let distance = self.outside_distance(q);
+                if distance < best_distance {
+                    best = q;
+                    best_distance = distance;
+                }
+            }
+        }
+        best
+    }
+
+    /// Maximal distance from point to lines of edges, which it lies outside of
+    fn outside_distance(&self, p: Vec2) -> f64 {
+        let n = self.vertices.len();
+        (0..n)
+            .map(|i| {
+                let (v1, v2) = (self.vertices[i], self.vertices[(i + 1) % n]);
+                (p - v1).area(v2 - v1) / (v2 - v1).len()
+            })
+            .fold(0.0, f64::max)
+    }
+
+    /// Maps point $(u, v)$ of unit square to first brillouin zone.
+    /// Uniformly distributed $u$ and $v$ give uniformly distributed momentums.
+    pub fn sample(&self, u: f64, v: f64) -> Vec2 {
+        self.to_first_bz(self.a + self.basis.0 * u + self.basis.1 * v)
     }
 
     /// Calculates maximum value of momentum in direction $\theta$ in first brillouin zone
     pub fn pmax(&self, theta: f64) -> f64 {
 
-        let oa = self.a;
-        let ob = self.b;
-        let oc = self.c;
-        let od = self.d;
         let l = Vec2::from_polar(1.0, theta);
 
         let mut res = -1.0;
 
-        let vs = vec![oa, ob, oc, od, oa];
-        for i in 0..4 {
-            let p = vs[i].area(vs[i + 1]) / l.area(vs[i + 1] - vs[i]);
+        let vs = &self.vertices;
+        let n = vs.len();
+        for i in 0..n {
+            let (v1, v2) = (vs[i], vs[(i + 1) % n]);
+            let p = v1.area(v2) / l.area(v2 - v1);
 
             if p < 0.0 {
                 continue;
@@ -93,8 +381,108 @@ impl BrillouinZone {
 
         res
     }
+
+    /// Measure of directions $\theta$, in which momentum of length `p` lies in first brillouin zone
+    pub fn angle_inside(&self, p: f64) -> f64 {
+        use std::f64::consts::PI;
+
+        let wrap = |x: f64| (x + PI).rem_euclid(2.0 * PI) - PI;
+        let vs = &self.vertices;
+        let mut res = 2.0 * PI;
+        for i in 0..vs.len() {
+            let (v1, v2) = (vs[i], vs[(i + 1) % vs.len()]);
+            let dist = v1.area(v2).abs() / (v2 - v1).len();
+            if p <= dist {
+                continue;
+            }
+            let normal = (v2 - v1).cross();
+            let phi = normal.y.atan2(normal.x);
+            let b1 = wrap(v1.y.atan2(v1.x) - phi);
+            let b2 = wrap(v2.y.atan2(v2.x) - phi);
+            let half = (dist / p).acos();
+            res -= (half.min(b1.max(b2)) - (-half).max(b1.min(b2))).max(0.0);
+        }
+        res
+    }
+}
+
+/// Finds all roots of $f(p) = 0$ on $(0, p\_{max})$, splitting it into `n` intervals.
+///
+/// Near nodes, where differences of $f$ change sign, extremum is found as zero of `derivative`
+/// and is added to nodes, so that two roots inside one interval are separated. Extremum,
+/// where $f$ vanishes up to rounding errors, is tangent root.
+pub fn ray_roots<F, D>(f: F, derivative: D, pmax: f64, n: usize) -> Vec<f64>
+    where F: Fn(f64) -> f64,
+          D: Fn(f64) -> f64
+{
+    let dp = pmax / n as f64;
+    let mut points: Vec<(f64, f64)> = (0..n + 1).map(|i| (dp * i as f64, f(dp * i as f64))).collect();
+    let scale = points.iter().fold(0.0, |acc: f64, x| acc.max(x.1.abs()));
+    for i in 1..n {
+        let (l, c, r) = (points[i - 1], points[i], points[i + 1]);
+        if (c.1 - l.1) * (r.1 - c.1) >= 0.0 {
+            continue;
+        }
+        let (mut a, mut b) = (l.0, r.0);
+        let da = derivative(a);
+        if da * derivative(b) >= 0.0 {
+            continue;
+        }
+        while b - a > 1e-14 * pmax {
+            let c = (a + b) / 2.0;
+            if derivative(c) * da > 0.0 {
+                a = c;
+            } else {
+                b = c;
+            }
+        }
+        let x = (a + b) / 2.0;
+        let fx = f(x);
+        points.push((x, if fx.abs() <= 1e-10 * scale { 0.0 } else { fx }));
+    }
+    points.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+    points.dedup_by(|a, b| a.0 - b.0 <= 1e-14 * pmax);
+
+    let mut roots = Vec::new();
+    for i in 1..points.len() {
+        let ((left, f_left), (right, f_right)) = (points[i - 1], points[i]);
+        if f_left == 0.0 && left > 0.0 {
+            roots.push(left);
+        } else if f_left * f_right < 0.0 {
+            let (mut a, mut b, mut fa) = (left, right, f_left);
+            while b - a > 1e-14 * pmax {
+                let c = (a + b) / 2.0;
+                let fc = f(c);
+                if fa * fc <= 0.0 {
+                    b = c;
+                } else {
+                    a = c;
+                    fa = fc;
+                }
+            }
+            roots.push((a + b) / 2.0);
+        }
+    }
+    roots
 }
 
+/// Clips convex polygon by half-plane $p \cdot n \leq c$
+fn clip(vertices: &[Vec2], n: Vec2, c: f64) -> Vec<Vec2> {
+    let mut res = Vec::new();
+    for i in 0..vertices.len() {
+        let (v1, v2) = (vertices[i], vertices[(i + 1) % vertices.len()]);
+        let (d1, d2) = (v1.dot(n) - c, v2.dot(n) - c);
+        if d1 <= 0.0 {
+            res.push(v1);
+        }
+        if d1 * d2 < 0.0 {
+            res.push(v1 + (v2 - v1) * (d1 / (d1 - d2)));
+        }
+    }
+    res
+}
+
+#[cfg(test)]
 macro_rules! assert_delta {
     ($x:expr, $y:expr, $d:expr) => {
         if !($x - $y < $d && $y - $x < $d) { panic!(); }
@@ -120,6 +508,97 @@ fn test_pmax() {
     assert_delta!(bz.pmax((-1.5f64).atan()), 3.25f64.sqrt(), 1e-10);
 }
 
+#[test]
+fn test_angle_inside() {
+    use std::f64::consts::PI;
+    let bz = BrillouinZone::new(Vec2::new(-1.0, -1.0),
+                                Vec2::new(1.0, -1.0),
+                                Vec2::new(-1.0, 1.0));
+    assert_delta!(bz.angle_inside(0.5), 2.0 * PI, 1e-10);
+    assert_delta!(bz.angle_inside(1.2), 2.0 * PI - 8.0 * (1.0 / 1.2f64).acos(), 1e-10);
+    assert_delta!(bz.angle_inside(1.5), 0.0, 1e-10);
+
+    let bz = BrillouinZone::new(Vec2::new(-4.0, -3.0),
+                                Vec2::new(0.0, -3.0),
+                                Vec2::new(0.0, 3.0));
+    let n = 100000;
+    let inside = (0..n).filter(|&i| bz.pmax(2.0 * PI * (i as f64 + 0.5) / n as f64) > 2.5).count();
+    assert_delta!(bz.angle_inside(2.5), 2.0 * PI * inside as f64 / n as f64, 1e-3);
+}
+
+#[cfg(test)]
+fn hexagonal() -> BrillouinZone {
+    let g = 2.0 * std::f64::consts::PI;
+    BrillouinZone::wigner_seitz(Vec2::new(g, -g / 3f64.sqrt()),
+                                Vec2::new(0.0, 2.0 * g / 3f64.sqrt()))
+}
+
+#[test]
+fn test_wigner_seitz() {
+    use std::f64::consts::PI;
+    let bz = hexagonal();
+    assert_eq!(bz.vertices().len(), 6);
+    // corners of hexagon are K points at distance $4 \pi / 3 a$
+    for v in bz.vertices() {
+        assert_delta!(v.len(), 4.0 * PI / 3.0, 1e-10);
+    }
+    assert_delta!(bz.pmax(0.0), 4.0 * PI / 3.0, 1e-10);
+    assert_delta!(bz.pmax(PI / 6.0), 2.0 * PI / 3f64.sqrt(), 1e-10);
+    assert_delta!(bz.pmax(PI / 2.0), 2.0 * PI / 3f64.sqrt(), 1e-10);
+    // parallelogram cell of the same area is centered at the same point
+    assert!((bz.b - bz.a - bz.basis().0).len() < 1e-12);
+    assert!((bz.c - bz.a - bz.basis().0 - bz.basis().1).len() < 1e-12);
+    assert!((bz.a + bz.c).len() < 1e-12 && (bz.b + bz.d).len() < 1e-12);
+
+    let bz = BrillouinZone::wigner_seitz(Vec2::new(2.0, 0.0), Vec2::new(0.0, 1.0));
+    assert_eq!(bz.vertices().len(), 4);
+    assert_delta!(bz.pmax(0.0), 1.0, 1e-10);
+    assert_delta!(bz.pmax(PI / 2.0), 0.5, 1e-10);
+}
+
+#[test]
+fn test_hexagonal_to_first_bz() {
+    let bz = hexagonal();
+    let (b1, b2) = bz.basis();
+    let points = [Vec2::new(15.3, -23.7), Vec2::new(-4.1, 3.9), Vec2::new(4.0, 0.1), Vec2::new(0.5, 0.3)];
+    for &p in &points {
+        let q = bz.to_first_bz(p);
+        assert!(bz.contains(q));
+        // difference is reciprocal lattice vector
+        let n1 = (p - q).dot(bz.dual_basis().0);
+        let n2 = (p - q).dot(bz.dual_basis().1);
+        assert_delta!(n1, n1.round(), 1e-10);
+        assert_delta!(n2, n2.round(), 1e-10);
+        assert!((bz.to_first_bz(q + b1 * 3.0 - b2 * 2.0) - q).len() < 1e-10);
+    }
+    assert_eq!(bz.to_first_bz(Vec2::new(0.5, 0.3)), Vec2::new(0.5, 0.3));
+}
+
+#[test]
+fn test_hexagonal_sample() {
+    use std::f64::consts::PI;
+    let bz = hexagonal();
+    let n = 200;
+    let mut mean = Vec2::zero();
+    let mut inner = 0;
+    for i in 0..n {
+        for j in 0..n {
+            let p = bz.sample((i as f64 + 0.5) / n as f64, (j as f64 + 0.5) / n as f64);
+            assert!(bz.contains(p));
+            mean += p;
+            if p.len() < 2.0 {
+                inner += 1;
+            }
+        }
+    }
+    mean /= (n * n) as f64;
+    assert!(mean.len() < 1e-2);
+    // fraction of points in circle is ratio of areas
+    let area = bz.basis().0.area(bz.basis().1).abs();
+    assert_delta!(inner as f64 / (n * n) as f64, 4.0 * PI / area, 1e-3);
+    assert_delta!(bz.angle_inside(4.0), 2.0 * PI - 12.0 * (2.0 * PI / 3f64.sqrt() / 4.0).acos(), 1e-10);
+}
+
 #[test]
 fn test_to_first_bz() {
     let mut bz = BrillouinZone::new(Vec2::new(-4.0, -3.0),
@@ -128,6 +607,8 @@ fn test_to_first_bz() {
     assert_eq!(bz.to_first_bz(Vec2::new(5.0, 3.0)),
                Vec2::new(-3.0, -3.0));
     assert!((bz.to_first_bz(Vec2::new(15.3, -23.7)) - Vec2::new(-0.7, 0.3)).len() < 1e-10);
+    assert_eq!((bz.a, bz.b, bz.c, bz.d),
+               (Vec2::new(-4.0, -3.0), Vec2::new(4.0, -3.0), Vec2::new(4.0, 3.0), Vec2::new(-4.0, 3.0)));
 
     bz = BrillouinZone::new(Vec2::new(-4.0, -3.0),
                             Vec2::new(0.0, -3.0),
@@ -136,3 +617,60 @@ fn test_to_first_bz() {
                Vec2::new(-3.0, -3.0));
     assert!((bz.to_first_bz(Vec2::new(15.3, -23.7)) - Vec2::new(-0.7, 0.3)).len() < 1e-10);
 }
+
+#[test]
+fn test_ray_roots() {
+    let roots = ray_roots(|p| (p * 3.0).sin(), |p| 3.0 * (p * 3.0).cos(), 4.0, 100);
+    assert_eq!(roots.len(), 3);
+    for (i, r) in roots.iter().enumerate() {
+        assert!((r - std::f64::consts::PI * (i + 1) as f64 / 3.0).abs() < 1e-12);
+    }
+    assert!(ray_roots(|p| p + 1.0, |_| 1.0, 4.0, 100).is_empty());
+    // double root and two roots inside one interval
+    let roots = ray_roots(|p| (p - 1.3).powi(2), |p| 2.0 * (p - 1.3), 4.0, 64);
+    assert_eq!(roots.len(), 1);
+    assert!((roots[0] - 1.3).abs() < 1e-12);
+    let roots = ray_roots(|p| (p - 1.3).powi(2) - 1e-4, |p| 2.0 * (p - 1.3), 4.0, 64);
+    assert_eq!(roots.len(), 2);
+    assert!((roots[0] - 1.29).abs() < 1e-12 && (roots[1] - 1.31).abs() < 1e-12);
+    assert!(ray_roots(|p| (p - 1.3).powi(2) + 1e-4, |p| 2.0 * (p - 1.3), 4.0, 64).is_empty());
+}
+
+#[test]
+fn test_default_gradient() {
+    use test_support::MexicanHat;
+    let m = MexicanHat::new();
+    for &p in &[Vec2::new(0.3, -0.2), Vec2::new(1.5, 1.1), Vec2::new(-0.9, 0.1)] {
+        let exact = p * 4.0 * (p.dot(p) - 1.0);
+        assert!((m.energy_gradient(p) - exact).len() < 1e-8);
+        assert_eq!(m.velocity(p), m.energy_gradient(p));
+    }
+}
+
+#[test]
+fn test_default_momentums() {
+    use std::f64::consts::PI;
+    use test_support::MexicanHat;
+    let m = MexicanHat::new();
+    for i in 0..16 {
+        let theta = 2.0 * PI * i as f64 / 16.0;
+        // inner and outer crossings of isoenergy ring
+        let ps = m.momentums(0.25, theta);
+        assert_eq!(ps.len(), 2);
+        assert_delta!(ps[0].len(), 0.5f64.sqrt(), 1e-10);
+        assert_delta!(ps[1].len(), 1.5f64.sqrt(), 1e-10);
+        let ps = m.momentums(4.0, theta);
+        assert_eq!(ps.len(), 1);
+        assert_delta!(ps[0].len(), 3f64.sqrt(), 1e-10);
+    }
+    assert!(m.momentums(16.0, 0.0).is_empty());
+    assert_eq!(m.momentums(16.0, PI / 4.0).len(), 1);
+    // bottom of ring is tangent root, close crossings of ring are separated
+    let ps = m.momentums(0.0, 0.3);
+    assert_eq!(ps.len(), 1);
+    assert_delta!(ps[0].len(), 1.0, 1e-8);
+    let ps = m.momentums(1e-8, 0.3);
+    assert_eq!(ps.len(), 2);
+    assert_delta!(ps[0].len(), (1.0 - 1e-4f64).sqrt(), 1e-10);
+    assert_delta!(ps[1].len(), (1.0 + 1e-4f64).sqrt(), 1e-10);
+}
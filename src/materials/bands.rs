@@ -0,0 +1,177 @@
+//! Several bands coupled by interband phonon scattering
+use material::{Material, MultiBand, BrillouinZone};
+use materials::{Phonons, DosTable};
+use linal::Vec2;
+
+/// Number of points in tabulated density of states
+const DOS_POINTS: usize = 128;
+
+#[derive(Clone)]
+/// Material with bands `bands`, which should share brillouin zone (zone of the first
+/// band is used). Intraband scattering is given by bands themselves, scattering from band
+/// `i` to band `j` is given by parameters `interband[i][j]` and density of states of band `j`.
+pub struct Bands<T: Material> {
+    pub bands: Vec<T>,
+    pub interband: Vec<Vec<Phonons>>,
+    dos: Vec<DosTable>,
+}
+
+impl<T: Material> Bands<T> {
+    /// Panics if there are no bands or `interband` is not $n \times n$ for $n$ bands
+    pub fn new(bands: Vec<T>, interband: Vec<Vec<Phonons>>) -> Bands<T> {
+        let n = bands.len();
+        assert!(n > 0, "there are no bands");
+        assert!(interband.len() == n && interband.iter().all(|row| row.len() == n),
+                "interband parameters are not {}x{} matrix",
+                n,
+                n);
+        let dos = bands.iter().map(|b| DosTable::new(b, DOS_POINTS)).collect();
+        Bands {
+            bands,
+            interband,
+            dos,
+        }
+    }
+}
+
+impl<T: Material> MultiBand for Bands<T> {
+    fn bands(&self) -> usize {
+        self.bands.len()
+    }
+    fn band_energy(&self, band: usize, p: Vec2) -> f64 {
+        self.bands[band].energy(p)
+    }
+    fn band_energy_gradient(&self, band: usize, p: Vec2) -> Vec2 {
+        self.bands[band].energy_gradient(p)
+    }
+    fn band_velocity(&self, band: usize, p: Vec2) -> Vec2 {
+        self.bands[band].velocity(p)
+    }
+    fn band_min_energy(&self, band: usize) -> f64 {
+        self.bands[band].min_energy()
+    }
+    fn band_max_energy(&self, band: usize) -> f64 {
+        self.bands[band].max_energy()
+    }
+    fn band_momentums(&self, band: usize, energy: f64, theta: f64) -> Vec<Vec2> {
+        self.bands[band].momentums(energy, theta)
+    }
+    fn zone(&self) -> &BrillouinZone {
+        self.bands[0].brillouin_zone()
+    }
+    fn band_optical_energy(&self, from: usize, to: usize) -> f64 {
+        if from == to {
+            self.bands[from].optical_energy()
+        } else {
+            self.interband[from][to].optical_energy
+        }
+    }
+    fn band_optical_scattering(&self, from: usize, to: usize, p: Vec2) -> f64 {
+        if from == to {
+            return self.bands[from].optical_scattering(p);
+        }
+        let e = self.band_energy(from, p) - self.band_optical_energy(from, to);
+        self.interband[from][to].optical_rate(self.dos[to].get(e))
+    }
+    fn band_optical_absorption(&self, from: usize, to: usize, p: Vec2) -> f64 {
+        if from == to {
+            return self.bands[from].optical_absorption(p);
+        }
+        let e = self.band_energy(from, p) + self.band_optical_energy(from, to);
+        self.interband[from][to].absorption_rate(self.dos[to].get(e))
+    }
+    fn band_acoustic_scattering(&self, from: usize, to: usize, p: Vec2) -> f64 {
+        if from == to {
+            return self.bands[from].acoustic_scattering(p);
+        }
+        let e = self.band_energy(from, p);
+        self.interband[from][to].acoustic_rate(self.dos[to].get(e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::PI;
+    use materials::Parabolic;
+    use boltzmann::initial_states_with;
+    use rng::Rng;
+    use {Fields, RunOptions, Stats, create_ensemble_seeded, run_ensemble};
+
+    fn two_bands(coupling: f64) -> Bands<Parabolic> {
+        let bz = BrillouinZone::new(Vec2::new(-1.0, -1.0),
+                                    Vec2::new(1.0, -1.0),
+                                    Vec2::new(-1.0, 1.0));
+        let intra = Phonons::new(5e-2, 3.0, 3.0);
+        let inter = Phonons::new(5e-4, coupling, coupling);
+        Bands::new(vec![Parabolic::new(10.0, bz.clone(), intra.clone()),
+                        Parabolic::new(30.0, bz, intra.clone())],
+                   vec![vec![intra.clone(), inter.clone()], vec![inter, intra]])
+    }
+
+    #[test]
+    #[should_panic(expected = "there are no bands")]
+    fn test_no_bands() {
+        Bands::<Parabolic>::new(vec![], vec![]);
+    }
+
+    #[test]
+    #[should_panic(expected = "not 2x2 matrix")]
+    fn test_interband_size() {
+        let m = two_bands(1.0);
+        let mut interband = m.interband.clone();
+        interband[1].pop();
+        Bands::new(m.bands, interband);
+    }
+
+    #[test]
+    fn test_interband_rates() {
+        let m = two_bands(1.0);
+        assert_eq!(m.bands(), 2);
+        let p = Vec2::new(0.1, 0.05);
+        // density of states of parabolic band is $2 \pi m$
+        assert!((m.band_acoustic_scattering(0, 1, p) - 30.0).abs() < 1e-2);
+        assert!((m.band_acoustic_scattering(1, 0, p) - 10.0).abs() < 1e-2);
+        assert_eq!(m.band_acoustic_scattering(0, 0, p), m.bands[0].acoustic_scattering(p));
+        assert!((m.band_optical_scattering(0, 1, p) - 30.0).abs() < 1e-2);
+        assert_eq!(m.band_optical_scattering(0, 1, Vec2::new(0.01, 0.0)), 0.0);
+        assert_eq!(m.band_optical_energy(1, 0), 5e-4);
+        assert_eq!(m.band_optical_energy(1, 1), 5e-2);
+        assert!((m.band_velocity(1, p) - p / 30.0).len() < 1e-12);
+        assert_eq!(m.band_momentums(1, 1.0, PI / 4.0).len(), 0);
+    }
+
+    #[test]
+    fn test_band_occupation() {
+        // Boltzmann distribution puts electrons into bands proportionally to mass
+        let m = two_bands(1.0);
+        let states = initial_states_with(&m, 7e-3, 20000, &mut Rng::new(5));
+        let upper = states.iter().filter(|s| s.0 == 1).count() as f64 / 20000.0;
+        assert!((upper - 0.75).abs() < 0.02, "{}", upper);
+    }
+
+    #[test]
+    fn test_interband_transitions() {
+        let f = &Fields::new((Vec2::new(0.05, 0.0), Vec2::zero(), Vec2::zero()),
+                             (0.0, 0.0, 0.0),
+                             (0.0, 0.0),
+                             0.0);
+        let m = &two_bands(1.0);
+        let stats = Stats::from_ensemble(&run_ensemble(&create_ensemble_seeded(20, m, 7e-3, 3),
+                                                       1e-1,
+                                                       20.0,
+                                                       f,
+                                                       &RunOptions::default()));
+        assert!(stats.interband > 0.0);
+        assert!(stats.interband <= stats.acoustic + stats.optical);
+
+        let m = &two_bands(0.0);
+        let stats = Stats::from_ensemble(&run_ensemble(&create_ensemble_seeded(20, m, 7e-3, 3),
+                                                       1e-1,
+                                                       20.0,
+                                                       f,
+                                                       &RunOptions::default()));
+        assert_eq!(stats.interband, 0.0);
+        assert!(stats.acoustic > 0.0);
+    }
+}
@@ -0,0 +1,281 @@
+//! Dirac cones of graphene-like materials
+use std::f64::consts::PI;
+use material::{Material, BrillouinZone};
+use materials::{Phonons, DosTable};
+use linal::Vec2;
+
+/// Number of points of density of states table of warped cone
+const DOS_POINTS: usize = 128;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+/// Inequivalent corners of hexagonal brillouin zone
+pub enum Valley {
+    K,
+    KPrime,
+}
+
+impl Valley {
+    /// $+1$ for K and $-1$ for K' valley
+    pub fn sign(self) -> f64 {
+        match self {
+            Valley::K => 1.0,
+            Valley::KPrime => -1.0,
+        }
+    }
+}
+
+#[derive(Clone)]
+/// Dirac cone in one valley of hexagonal lattice with optional gap and trigonal warping:
+/// $E(q) = \sqrt{\Delta\^2 + v\^2 q\^2} + \xi w q\^2 \cos 3 \varphi$,
+/// where $\xi = \pm 1$ for K and K' valley.
+///
+/// Momentum $q$ is measured from Dirac point. Brillouin zone is hexagonal Wigner–Seitz cell
+/// of lattice with constant `lattice_constant`, centered at Dirac point; $\cos 3 \varphi$
+/// vanishes at normals of its edges, so energy is continuous across them. Warping `w` should
+/// satisfy $w q \ll v$ on the whole zone, so that cone stays convex. Density of states
+/// of warped cone is tabulated.
+pub struct Dirac {
+    pub velocity: f64,
+    pub gap: f64,
+    pub warping: f64,
+    pub valley: Valley,
+    pub phonons: Phonons,
+    bz: BrillouinZone,
+    max_energy: f64,
+    dos: DosTable,
+}
+
+impl Dirac {
+    pub fn new(velocity: f64,
+               gap: f64,
+               warping: f64,
+               lattice_constant: f64,
+               valley: Valley,
+               phonons: Phonons)
+               -> Dirac {
+        let g = 2.0 * PI / lattice_constant;
+        let b1 = Vec2::new(g, -g / 3f64.sqrt());
+        let b2 = Vec2::new(0.0, 2.0 * g / 3f64.sqrt());
+        let mut m = Dirac {
+            velocity,
+            gap,
+            warping,
+            valley,
+            phonons,
+            bz: BrillouinZone::wigner_seitz(b1, b2),
+            max_energy: 0.0,
+            dos: DosTable::empty(),
+        };
+        m.max_energy = m.bz.vertices().iter().map(|&v| m.energy(v)).fold(0.0, f64::max);
+        if warping != 0.0 {
+            m.dos = DosTable::new(&m, DOS_POINTS);
+        }
+        m
+    }
+
+    /// $\int\limits\_{BZ} \delta(E(q)-E) d\^{2} q$, tabulated for warped cone
+    fn density_of_states(&self, energy: f64) -> f64 {
+        if energy <= self.gap {
+            return 0.0;
+        }
+        if self.warping != 0.0 {
+            return self.dos.get(energy);
+        }
+        let q = (energy * energy - self.gap * self.gap).sqrt() / self.velocity;
+        energy / (self.velocity * self.velocity) * self.bz.angle_inside(q)
+    }
+}
+
+impl Material for Dirac {
+    fn energy(&self, p: Vec2) -> f64 {
+        let q2 = p.dot(p);
+        let mut e = (self.gap * self.gap + self.velocity * self.velocity * q2).sqrt();
+        if q2 > 0.0 {
+            e += self.valley.sign() * self.warping * (p.x * p.x * p.x - 3.0 * p.x * p.y * p.y) /
+                 q2.sqrt();
+        }
+        e
+    }
+    /// Gradient is discontinuous at tip of gapless cone, zero is returned there
+    fn energy_gradient(&self, p: Vec2) -> Vec2 {
+        let q = p.len();
+        if q == 0.0 {
+            return Vec2::zero();
+        }
+        let e0 = (self.gap * self.gap + self.velocity * self.velocity * q * q).sqrt();
+        let f = p.x * p.x * p.x - 3.0 * p.x * p.y * p.y;
+        let df = Vec2::new(3.0 * (p.x * p.x - p.y * p.y), -6.0 * p.x * p.y);
+        p * (self.velocity * self.velocity / e0) +
+        (df / q - p * (f / (q * q * q))) * (self.valley.sign() * self.warping)
+    }
+    fn min_energy(&self) -> f64 {
+        self.gap
+    }
+    fn max_energy(&self) -> f64 {
+        self.max_energy
+    }
+    /// Along ray $\sqrt{\Delta\^2 + v\^2 q\^2} = E - s q\^2$ with $s = \xi w \cos 3 \theta$,
+    /// which is quadratic equation for $q\^2$
+    fn momentums(&self, energy: f64, theta: f64) -> Vec<Vec2> {
+        let pmax = self.bz.pmax(theta);
+        let s = self.valley.sign() * self.warping * (3.0 * theta).cos();
+        let v2 = self.velocity * self.velocity;
+        let c = energy * energy - self.gap * self.gap;
+        let mut xs = Vec::new();
+        if s.abs() < 1e-12 * v2 {
+            xs.push(c / v2);
+        } else {
+            let b = 2.0 * energy * s + v2;
+            let d = b * b - 4.0 * s * s * c;
+            if d >= 0.0 {
+                // numerically stable roots of $s\^2 x\^2 - b x + c = 0$
+                let t = (b + b.signum() * d.sqrt()) / 2.0;
+                xs.push(c / t);
+                xs.push(t / (s * s));
+            }
+        }
+        let mut ps: Vec<f64> = xs.into_iter()
+            .filter(|&x| x >= 0.0 && energy - s * x >= 0.0)
+            .map(f64::sqrt)
+            .filter(|&p| p < pmax)
+            .collect();
+        ps.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        ps.into_iter().map(|p| Vec2::from_polar(p, theta)).collect()
+    }
+    fn brillouin_zone(&self) -> &BrillouinZone {
+        &self.bz
+    }
+    fn optical_energy(&self) -> f64 {
+        self.phonons.optical_energy
+    }
+    fn optical_scattering(&self, p: Vec2) -> f64 {
+        let e = self.energy(p) - self.optical_energy();
+        self.phonons.optical_rate(self.density_of_states(e))
+    }
+    fn optical_absorption(&self, p: Vec2) -> f64 {
+        let e = self.energy(p) + self.optical_energy();
+        self.phonons.absorption_rate(self.density_of_states(e))
+    }
+    fn acoustic_scattering(&self, p: Vec2) -> f64 {
+        self.phonons.acoustic_rate(self.density_of_states(self.energy(p)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use probability::probability;
+
+    fn gradient_error<T: Material>(m: &T, p: Vec2) -> f64 {
+        let h = 1e-6;
+        let dx = Vec2::new(h, 0.0);
+        let dy = Vec2::new(0.0, h);
+        let fd = Vec2::new(m.energy(p + dx) - m.energy(p - dx),
+                           m.energy(p + dy) - m.energy(p - dy)) / (2.0 * h);
+        (fd - m.energy_gradient(p)).len()
+    }
+
+    #[test]
+    fn test_dirac_gradient() {
+        for &valley in &[Valley::K, Valley::KPrime] {
+            let m = Dirac::new(1.0, 0.1, 0.05, 1.0, valley, Phonons::new(0.2, 0.1, 0.1));
+            assert!(gradient_error(&m, Vec2::new(0.3, -0.7)) < 1e-8);
+            assert!(gradient_error(&m, Vec2::new(-0.02, 0.01)) < 1e-8);
+            assert_eq!(m.energy(Vec2::zero()), 0.1);
+        }
+        let m = Dirac::new(1.0, 0.0, 0.0, 1.0, Valley::K, Phonons::new(0.2, 0.1, 0.1));
+        assert_eq!(m.velocity(Vec2::zero()), Vec2::zero());
+        assert!((m.velocity(Vec2::new(1e-9, 0.0)) - Vec2::new(1.0, 0.0)).len() < 1e-12);
+    }
+
+    #[test]
+    fn test_dirac_momentums() {
+        for &valley in &[Valley::K, Valley::KPrime] {
+            let m = Dirac::new(1.0, 0.1, 0.05, 1.0, valley, Phonons::new(0.2, 0.1, 0.1));
+            for i in 0..64 {
+                let theta = 2.0 * PI * i as f64 / 64.0;
+                for &e in &[0.1, 0.5, 2.0] {
+                    let ps = m.momentums(e, theta);
+                    assert_eq!(ps.len(), 1);
+                    assert!((m.energy(ps[0]) - e).abs() < 1e-12);
+                }
+                assert!(m.momentums(0.05, theta).is_empty());
+            }
+        }
+        // warping of K and K' valleys are related by time reversal
+        let k = Dirac::new(1.0, 0.1, 0.05, 1.0, Valley::K, Phonons::new(0.2, 0.1, 0.1));
+        let kp = Dirac::new(1.0, 0.1, 0.05, 1.0, Valley::KPrime, Phonons::new(0.2, 0.1, 0.1));
+        let p = Vec2::new(0.3, 0.2);
+        assert!((k.energy(p) - kp.energy(-p)).abs() < 1e-14);
+    }
+
+    #[test]
+    fn test_dirac_rates() {
+        let m = Dirac::new(1.0, 0.1, 0.0, 1.0, Valley::K, Phonons::new(0.2, 0.1, 0.1));
+        for &e in &[0.3, 1.0] {
+            let p = m.momentums(e, 0.5)[0];
+            let dos = probability(e, &m, 1e-6);
+            assert!((m.acoustic_scattering(p) - 0.1 * dos / (2.0 * PI)).abs() < 1e-6);
+        }
+        assert_eq!(m.optical_scattering(m.momentums(0.25, 0.0)[0]), 0.0);
+    }
+
+    #[test]
+    fn test_warped_rates() {
+        for &valley in &[Valley::K, Valley::KPrime] {
+            let m = Dirac::new(1.0, 0.1, 0.05, 1.0, valley, Phonons::new(0.2, 0.1, 0.1));
+            // density of states includes warping
+            for &e in &[0.5, 2.0] {
+                let p = m.momentums(e, 0.3)[0];
+                let rate = 0.1 * probability(e, &m, 1e-6) / (2.0 * PI);
+                assert!((m.acoustic_scattering(p) - rate).abs() < 1e-3 * rate);
+            }
+        }
+    }
+
+    #[test]
+    fn test_warped_cone() {
+        use validation::check;
+
+        // energy and its image behind the opposite edge of zone agree
+        for &valley in &[Valley::K, Valley::KPrime] {
+            let m = Dirac::new(1.0, 0.1, 0.05, 1.0, valley, Phonons::new(0.2, 0.1, 0.1));
+            let report = check(&m, 16, 1e-4);
+            assert!(report.is_ok(), "{:?}", report.violations);
+        }
+    }
+
+    #[test]
+    fn test_gapless_tip() {
+        use particle::Particle;
+        use {Fields, RunOptions, Stats, create_ensemble_seeded, run_ensemble};
+
+        let m = &Dirac::new(1.0, 0.0, 0.02, 1.0, Valley::K, Phonons::new(0.2, 0.5, 0.5));
+        assert_eq!(probability(0.0, m, 1e-3), 0.0);
+        // density of states and all rates vanish at the tip
+        let tip = Vec2::zero();
+        assert_eq!(m.acoustic_scattering(tip), 0.0);
+        assert_eq!(m.optical_scattering(tip), 0.0);
+        assert_eq!(m.optical_absorption(tip), 0.0);
+        // electron at rest in the tip is accelerated by field against it
+        let f = &Fields::new((Vec2::new(0.05, 0.0), Vec2::zero(), Vec2::zero()),
+                             (0.0, 0.0, 0.0),
+                             (0.0, 0.0),
+                             0.0);
+        let s = Particle::new(m, tip, 1).run(1e-2, 5.0, f);
+        assert!(s.energy > 0.0);
+        assert!(s.average_speed.x < -0.1, "{}", s.average_speed.x);
+        assert!(s.displacement.x < 0.0);
+        assert_eq!(s.failed, 0);
+
+        let f = &Fields::new((Vec2::new(0.05, 0.0), Vec2::zero(), Vec2::zero()),
+                             (0.5, 0.0, 0.0),
+                             (0.0, 0.0),
+                             0.0);
+        let ensemble = create_ensemble_seeded(20, m, 0.05, 7);
+        let options = &RunOptions::default();
+        let stats = Stats::from_ensemble(&run_ensemble(&ensemble, 1e-2, 5.0, f, options));
+        assert!(stats.current.x.is_finite() && stats.current.y.is_finite());
+        assert!(stats.energy.is_finite());
+    }
+}
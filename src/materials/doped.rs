@@ -0,0 +1,120 @@
+//! Material with ionized impurities
+use material::{MultiBand, BrillouinZone};
+use scattering::{ScatteringMechanism, Impurities};
+use linal::Vec2;
+
+#[derive(Clone)]
+/// Material `material` doped with `impurities`, which scatter electron in addition to
+/// mechanisms of material itself.
+///
+/// It is `MultiBand`, but not `Material` even for one band, as `MultiBand` is implemented
+/// for every `Material`. So doped material can't be band of `Bands` or valley of `Valleys`
+/// (dope the whole `Bands` or `Valleys` instead), and functions taking `Material`, such as
+/// `validation::check`, should be given `material`.
+pub struct Doped<T: MultiBand> {
+    pub material: T,
+    pub impurities: Impurities,
+}
+
+impl<T: MultiBand> Doped<T> {
+    pub fn new(material: T, impurities: Impurities) -> Doped<T> {
+        Doped {
+            material,
+            impurities,
+        }
+    }
+}
+
+impl<T: MultiBand> MultiBand for Doped<T> {
+    fn bands(&self) -> usize {
+        self.material.bands()
+    }
+    fn band_energy(&self, band: usize, p: Vec2) -> f64 {
+        self.material.band_energy(band, p)
+    }
+    fn band_energy_gradient(&self, band: usize, p: Vec2) -> Vec2 {
+        self.material.band_energy_gradient(band, p)
+    }
+    fn band_velocity(&self, band: usize, p: Vec2) -> Vec2 {
+        self.material.band_velocity(band, p)
+    }
+    fn band_min_energy(&self, band: usize) -> f64 {
+        self.material.band_min_energy(band)
+    }
+    fn band_max_energy(&self, band: usize) -> f64 {
+        self.material.band_max_energy(band)
+    }
+    fn band_momentums(&self, band: usize, energy: f64, theta: f64) -> Vec<Vec2> {
+        self.material.band_momentums(band, energy, theta)
+    }
+    fn zone(&self) -> &BrillouinZone {
+        self.material.zone()
+    }
+    fn band_optical_energy(&self, from: usize, to: usize) -> f64 {
+        self.material.band_optical_energy(from, to)
+    }
+    fn band_optical_scattering(&self, from: usize, to: usize, p: Vec2) -> f64 {
+        self.material.band_optical_scattering(from, to, p)
+    }
+    fn band_optical_absorption(&self, from: usize, to: usize, p: Vec2) -> f64 {
+        self.material.band_optical_absorption(from, to, p)
+    }
+    fn band_acoustic_scattering(&self, from: usize, to: usize, p: Vec2) -> f64 {
+        self.material.band_acoustic_scattering(from, to, p)
+    }
+    fn valleys(&self) -> usize {
+        self.material.valleys()
+    }
+    fn valley(&self, band: usize) -> usize {
+        self.material.valley(band)
+    }
+    fn intervalley_energy(&self, from: usize, to: usize) -> f64 {
+        self.material.intervalley_energy(from, to)
+    }
+    fn intervalley_scattering(&self, from: usize, to: usize, p: Vec2) -> f64 {
+        self.material.intervalley_scattering(from, to, p)
+    }
+    fn band_mechanisms(&self) -> Vec<Box<dyn ScatteringMechanism>> {
+        let mut mechanisms = self.material.band_mechanisms();
+        mechanisms.push(Box::new(self.impurities.clone()));
+        mechanisms
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use materials::{Parabolic, Phonons};
+    use {Fields, RunOptions, Stats, create_ensemble_seeded, run_ensemble};
+
+    #[test]
+    fn test_impurity_scattering() {
+        let bz = BrillouinZone::new(Vec2::new(-1.0, -1.0),
+                                    Vec2::new(1.0, -1.0),
+                                    Vec2::new(-1.0, 1.0));
+        let clean = &Parabolic::new(10.0, bz, Phonons::new(5e-2, 0.1, 0.1));
+        let doped = &Doped::new(clean.clone(), Impurities::new(0.3, 5.0, 10.0));
+        let f = &Fields::new((Vec2::new(0.1, 0.0), Vec2::zero(), Vec2::zero()),
+                             (0.0, 0.0, 0.0),
+                             (0.0, 0.0),
+                             0.0);
+        let clean = Stats::from_ensemble(&run_ensemble(&create_ensemble_seeded(50, clean, 7e-3, 3),
+                                                       1e-1,
+                                                       20.0,
+                                                       f,
+                                                       &RunOptions::default()));
+        let doped = Stats::from_ensemble(&run_ensemble(&create_ensemble_seeded(50, doped, 7e-3, 3),
+                                                       1e-1,
+                                                       20.0,
+                                                       f,
+                                                       &RunOptions::default()));
+        assert_eq!(doped.mechanisms.last().unwrap().name, "impurity");
+        assert!(doped.mechanisms.last().unwrap().count > 0.0);
+        assert!(doped.acoustic > 0.0);
+        // impurities reduce drift velocity
+        assert!(clean.current.x < doped.current.x && doped.current.x < 0.0,
+                "{} {}",
+                clean.current.x,
+                doped.current.x);
+    }
+}
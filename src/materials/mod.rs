@@ -0,0 +1,202 @@
+//! Ready-made implementations of `Material`
+use std::f64::consts::PI;
+use material::Material;
+use probability::probability;
+
+mod parabolic;
+mod superlattice;
+mod dirac;
+mod tabulated;
+mod wannier;
+mod bands;
+mod valleys;
+mod doped;
+
+pub use self::parabolic::{Parabolic, AnisotropicParabolic};
+pub use self::superlattice::{Superlattice1D, Superlattice2D, esaki_tsu};
+pub use self::dirac::{Dirac, Valley};
+pub use self::tabulated::Tabulated;
+pub use self::wannier::Wannier;
+pub use self::bands::Bands;
+pub use self::valleys::{Valleys, IntervalleyPhonons};
+pub use self::doped::Doped;
+pub use error::LoadError;
+
+#[derive(Clone)]
+/// Parameters of electron-phonon interaction in deformation potential approximation.
+///
+/// Scattering probability is $W = 2 \pi |M|\^2 \int \delta(E(p') - E) d\^2 p' / (2 \pi)\^2$,
+/// where $|M|\^2$ is `acoustic_coupling` or `optical_coupling`. Optical phonon emission and
+/// absorption are weighted by $N\_q + 1$ and $N\_q$, where $N\_q$ is Bose–Einstein occupation
+/// at lattice `temperature`.
+pub struct Phonons {
+    /// optical phonon energy
+    pub optical_energy: f64,
+    /// $D\_{ac}\^2 k T / \rho v\_s\^2$
+    pub acoustic_coupling: f64,
+    /// $D\_{op}\^2 / 2 \rho \omega\_{op}$
+    pub optical_coupling: f64,
+    /// lattice temperature, only spontaneous emission of optical phonons happens at zero one
+    pub temperature: f64,
+}
+
+impl Phonons {
+    pub fn new(optical_energy: f64, acoustic_coupling: f64, optical_coupling: f64) -> Phonons {
+        Phonons {
+            optical_energy,
+            acoustic_coupling,
+            optical_coupling,
+            temperature: 0.0,
+        }
+    }
+
+    /// Builds couplings from deformation potentials `d_ac` and `d_op`, mass `density`,
+    /// `sound_velocity` and lattice `temperature` (in units $\hbar = k = 1$)
+    pub fn deformation_potential(d_ac: f64,
+                                 d_op: f64,
+                                 density: f64,
+                                 sound_velocity: f64,
+                                 optical_energy: f64,
+                                 temperature: f64)
+                                 -> Phonons {
+        Phonons {
+            temperature,
+            ..Phonons::new(optical_energy,
+                           d_ac * d_ac * temperature / (density * sound_velocity * sound_velocity),
+                           d_op * d_op / (2.0 * density * optical_energy))
+        }
+    }
+
+    /// Bose–Einstein occupation of optical phonons $N\_q = 1 / (e\^{\hbar \omega / k T} - 1)$
+    pub fn occupation(&self) -> f64 {
+        if self.temperature <= 0.0 {
+            return 0.0;
+        }
+        1.0 / (self.optical_energy / self.temperature).exp_m1()
+    }
+
+    /// Acoustic scattering probability for given density of final states
+    pub fn acoustic_rate(&self, dos: f64) -> f64 {
+        self.acoustic_coupling * dos / (2.0 * PI)
+    }
+
+    /// Optical phonon emission probability for given density of final states
+    pub fn optical_rate(&self, dos: f64) -> f64 {
+        (self.occupation() + 1.0) * self.optical_coupling * dos / (2.0 * PI)
+    }
+
+    /// Optical phonon absorption probability for given density of final states
+    pub fn absorption_rate(&self, dos: f64) -> f64 {
+        self.occupation() * self.optical_coupling * dos / (2.0 * PI)
+    }
+}
+
+#[derive(Clone)]
+/// Density of states $\int\limits\_{BZ} \delta(E(p)-E) d\^{2} p$, tabulated on uniform energy grid
+struct DosTable {
+    min: f64,
+    step: f64,
+    values: Vec<f64>,
+}
+
+impl DosTable {
+    fn empty() -> DosTable {
+        DosTable {
+            min: 0.0,
+            step: 1.0,
+            values: vec![],
+        }
+    }
+
+    /// Tabulates density of states of material in `n` points between minimum and maximum of
+    /// energy, table of flat band is empty
+    fn new<T: Material>(m: &T, n: usize) -> DosTable {
+        let (min, max) = (m.min_energy(), m.max_energy());
+        if max <= min {
+            return DosTable {
+                min,
+                step: 0.0,
+                values: vec![],
+            };
+        }
+        let step = (max - min) / n as f64;
+        let values = (0..n)
+            .map(|i| probability(min + step * (i as f64 + 0.5), m, 1e-4))
+            .collect();
+        DosTable {
+            min,
+            step,
+            values,
+        }
+    }
+
+    /// Tabulates density of states in `n` points by linear triangle method from energies
+    /// `grid[i][j]` on periodic uniform grid over brillouin zone of area `area`. Every cell
+    /// of grid is split into two triangles with linear energy on each of them. Density
+    /// of states of flat band is delta function, so table is empty and gives zero.
+    fn from_grid(area: f64, grid: &[Vec<f64>], n: usize) -> DosTable {
+        let (k1, k2) = (grid.len(), grid[0].len());
+        let min = grid.iter().flat_map(|row| row.iter()).cloned().fold(f64::INFINITY, f64::min);
+        let max = grid.iter().flat_map(|row| row.iter()).cloned().fold(f64::NEG_INFINITY, f64::max);
+        if max <= min {
+            return DosTable {
+                min,
+                step: 0.0,
+                values: vec![],
+            };
+        }
+        let step = (max - min) / n as f64;
+        let area = area / (2 * k1 * k2) as f64;
+        let mut values = vec![0.0; n];
+        for i in 0..k1 {
+            for j in 0..k2 {
+                let (e00, e10) = (grid[i][j], grid[(i + 1) % k1][j]);
+                let (e01, e11) = (grid[i][(j + 1) % k2], grid[(i + 1) % k1][(j + 1) % k2]);
+                for es in &[[e00, e10, e11], [e00, e01, e11]] {
+                    let mut es = *es;
+                    es.sort_by(|a, b| a.partial_cmp(b).unwrap());
+                    let [e1, e2, e3] = es;
+                    if e3 - e1 <= 0.0 {
+                        continue;
+                    }
+                    let first = ((e1 - min) / step - 0.5).ceil().max(0.0) as usize;
+                    for (k, value) in values.iter_mut().enumerate().skip(first) {
+                        let e = min + step * (k as f64 + 0.5);
+                        if e >= e3 {
+                            break;
+                        }
+                        *value += 2.0 * area / (e3 - e1) *
+                                  if e < e2 {
+                                      (e - e1) / (e2 - e1)
+                                  } else {
+                                      (e3 - e) / (e3 - e2)
+                                  };
+                    }
+                }
+            }
+        }
+        DosTable {
+            min,
+            step,
+            values,
+        }
+    }
+
+    /// Linear interpolation of tabulated values, zero outside of band
+    fn get(&self, energy: f64) -> f64 {
+        let n = self.values.len();
+        if n == 0 || energy < self.min || energy > self.min + self.step * n as f64 {
+            return 0.0;
+        }
+        let x = (energy - self.min) / self.step - 0.5;
+        if x <= 0.0 {
+            return self.values[0];
+        }
+        let i = x.floor() as usize;
+        if i + 1 >= n {
+            return self.values[n - 1];
+        }
+        let t = x - i as f64;
+        self.values[i] * (1.0 - t) + self.values[i + 1] * t
+    }
+}
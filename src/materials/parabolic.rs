@@ -0,0 +1,270 @@
+//! Parabolic bands with isotropic and anisotropic effective mass
+use material::{Material, BrillouinZone};
+use materials::Phonons;
+use linal::Vec2;
+
+#[derive(Clone)]
+/// Isotropic parabolic band $E(p) = p\^2 / 2 m$
+pub struct Parabolic {
+    pub mass: f64,
+    pub phonons: Phonons,
+    bz: BrillouinZone,
+}
+
+impl Parabolic {
+    pub fn new(mass: f64, bz: BrillouinZone, phonons: Phonons) -> Parabolic {
+        Parabolic {
+            mass,
+            phonons,
+            bz,
+        }
+    }
+
+    /// $\int\limits\_{BZ} \delta(E(p)-E) d\^{2} p$
+    fn density_of_states(&self, energy: f64) -> f64 {
+        if energy < 0.0 {
+            return 0.0;
+        }
+        self.mass * self.bz.angle_inside((2.0 * self.mass * energy).sqrt())
+    }
+}
+
+impl Material for Parabolic {
+    fn energy(&self, p: Vec2) -> f64 {
+        p.dot(p) / (2.0 * self.mass)
+    }
+    fn energy_gradient(&self, p: Vec2) -> Vec2 {
+        p / self.mass
+    }
+    fn min_energy(&self) -> f64 {
+        0.0
+    }
+    fn max_energy(&self) -> f64 {
+        self.bz.vertices().iter().map(|&v| self.energy(v)).fold(0.0, f64::max)
+    }
+    fn momentums(&self, energy: f64, theta: f64) -> Vec<Vec2> {
+        let mut ms = Vec::new();
+        let p = (2.0 * self.mass * energy).sqrt();
+        if energy >= 0.0 && p < self.bz.pmax(theta) {
+            ms.push(Vec2::from_polar(p, theta));
+        }
+        ms
+    }
+    fn brillouin_zone(&self) -> &BrillouinZone {
+        &self.bz
+    }
+    fn optical_energy(&self) -> f64 {
+        self.phonons.optical_energy
+    }
+    fn optical_scattering(&self, p: Vec2) -> f64 {
+        let e = self.energy(p) - self.optical_energy();
+        self.phonons.optical_rate(self.density_of_states(e))
+    }
+    fn optical_absorption(&self, p: Vec2) -> f64 {
+        let e = self.energy(p) + self.optical_energy();
+        self.phonons.absorption_rate(self.density_of_states(e))
+    }
+    fn acoustic_scattering(&self, p: Vec2) -> f64 {
+        self.phonons.acoustic_rate(self.density_of_states(self.energy(p)))
+    }
+}
+
+/// Rotates vector by angle `alpha` counterclockwise
+fn rotate(p: Vec2, alpha: f64) -> Vec2 {
+    let (s, c) = alpha.sin_cos();
+    Vec2::new(p.x * c - p.y * s, p.x * s + p.y * c)
+}
+
+#[derive(Clone)]
+/// Parabolic band with anisotropic effective mass.
+///
+/// Principal axes of mass tensor are rotated by angle `alpha` from x and y axes:
+/// $E(p) = p\_1\^2 / 2 m\_1 + p\_2\^2 / 2 m\_2$, where $p\_1, p\_2$ are components along principal axes.
+pub struct AnisotropicParabolic {
+    pub mass: (f64, f64),
+    pub alpha: f64,
+    pub phonons: Phonons,
+    bz: BrillouinZone,
+    /// brillouin zone in coordinates $q\_i = p\_i / \sqrt{m\_i}$, where spectrum is isotropic
+    q_bz: BrillouinZone,
+}
+
+impl AnisotropicParabolic {
+    pub fn new(mass: (f64, f64), alpha: f64, bz: BrillouinZone, phonons: Phonons) -> AnisotropicParabolic {
+        let mut m = AnisotropicParabolic {
+            mass,
+            alpha,
+            phonons,
+            q_bz: bz.clone(),
+            bz,
+        };
+        let vertices = m.bz.vertices().iter().map(|&v| m.to_q(v)).collect();
+        let basis = (m.to_q(m.bz.basis().0), m.to_q(m.bz.basis().1));
+        m.q_bz = BrillouinZone::polygon(vertices, basis);
+        m
+    }
+
+    fn to_q(&self, p: Vec2) -> Vec2 {
+        let p = rotate(p, -self.alpha);
+        Vec2::new(p.x / self.mass.0.sqrt(), p.y / self.mass.1.sqrt())
+    }
+
+    /// $\int\limits\_{BZ} \delta(E(p)-E) d\^{2} p$
+    fn density_of_states(&self, energy: f64) -> f64 {
+        if energy < 0.0 {
+            return 0.0;
+        }
+        (self.mass.0 * self.mass.1).sqrt() * self.q_bz.angle_inside((2.0 * energy).sqrt())
+    }
+}
+
+impl Material for AnisotropicParabolic {
+    fn energy(&self, p: Vec2) -> f64 {
+        let p = rotate(p, -self.alpha);
+        p.x * p.x / (2.0 * self.mass.0) + p.y * p.y / (2.0 * self.mass.1)
+    }
+    fn energy_gradient(&self, p: Vec2) -> Vec2 {
+        let p = rotate(p, -self.alpha);
+        rotate(Vec2::new(p.x / self.mass.0, p.y / self.mass.1), self.alpha)
+    }
+    fn min_energy(&self) -> f64 {
+        0.0
+    }
+    fn max_energy(&self) -> f64 {
+        self.bz.vertices().iter().map(|&v| self.energy(v)).fold(0.0, f64::max)
+    }
+    fn momentums(&self, energy: f64, theta: f64) -> Vec<Vec2> {
+        let mut ms = Vec::new();
+        if energy < 0.0 {
+            return ms;
+        }
+        let p = (energy / self.energy_polar(1.0, theta)).sqrt();
+        if p < self.bz.pmax(theta) {
+            ms.push(Vec2::from_polar(p, theta));
+        }
+        ms
+    }
+    fn brillouin_zone(&self) -> &BrillouinZone {
+        &self.bz
+    }
+    fn optical_energy(&self) -> f64 {
+        self.phonons.optical_energy
+    }
+    fn optical_scattering(&self, p: Vec2) -> f64 {
+        let e = self.energy(p) - self.optical_energy();
+        self.phonons.optical_rate(self.density_of_states(e))
+    }
+    fn optical_absorption(&self, p: Vec2) -> f64 {
+        let e = self.energy(p) + self.optical_energy();
+        self.phonons.absorption_rate(self.density_of_states(e))
+    }
+    fn acoustic_scattering(&self, p: Vec2) -> f64 {
+        self.phonons.acoustic_rate(self.density_of_states(self.energy(p)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use probability::probability;
+    use std::f64::consts::PI;
+
+    fn square() -> BrillouinZone {
+        BrillouinZone::new(Vec2::new(-1.0, -1.0),
+                           Vec2::new(1.0, -1.0),
+                           Vec2::new(-1.0, 1.0))
+    }
+
+    fn gradient_error<T: Material>(m: &T, p: Vec2) -> f64 {
+        let h = 1e-6;
+        let dx = Vec2::new(h, 0.0);
+        let dy = Vec2::new(0.0, h);
+        let fd = Vec2::new(m.energy(p + dx) - m.energy(p - dx),
+                           m.energy(p + dy) - m.energy(p - dy)) / (2.0 * h);
+        (fd - m.energy_gradient(p)).len()
+    }
+
+    #[test]
+    fn test_parabolic() {
+        let m = Parabolic::new(10.0, square(), Phonons::new(5e-2, 0.1, 0.1));
+        assert!((m.max_energy() - 0.1).abs() < 1e-12);
+        let p = Vec2::new(0.3, -0.7);
+        assert!(gradient_error(&m, p) < 1e-8);
+        let ps = m.momentums(0.03, 1.0);
+        assert_eq!(ps.len(), 1);
+        assert!((m.energy(ps[0]) - 0.03).abs() < 1e-12);
+        assert!(m.momentums(0.09, PI / 4.0).len() == 1);
+        assert!(m.momentums(0.09, 0.0).is_empty());
+        assert_eq!(m.optical_scattering(Vec2::new(0.1, 0.0)), 0.0);
+    }
+
+    #[test]
+    fn test_parabolic_rates() {
+        let m = Parabolic::new(10.0, square(), Phonons::new(5e-2, 0.1, 0.2));
+        for &e in &[0.02, 0.07] {
+            let p = m.momentums(e, PI / 4.0)[0];
+            let dos = probability(e, &m, 1e-6);
+            assert!((m.acoustic_scattering(p) - 0.1 * dos / (2.0 * PI)).abs() < 1e-4);
+        }
+        let p = m.momentums(0.09, PI / 4.0)[0];
+        let dos = probability(0.04, &m, 1e-6);
+        assert!((m.optical_scattering(p) - 0.2 * dos / (2.0 * PI)).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_optical_absorption() {
+        let phonons = Phonons { temperature: 0.02, ..Phonons::new(1e-2, 0.05, 0.1) };
+        let n = 1.0 / (0.5f64.exp() - 1.0);
+        assert!((phonons.occupation() - n).abs() < 1e-12);
+        let m = Parabolic::new(10.0, square(), phonons);
+        let p = m.momentums(0.03, 0.3)[0];
+        // density of states of parabolic band does not depend on energy
+        assert!((m.optical_scattering(p) / m.optical_absorption(p) - 0.5f64.exp()).abs() < 1e-9);
+        let cold = Parabolic::new(10.0, square(), Phonons::new(1e-2, 0.05, 0.1));
+        assert_eq!(cold.optical_absorption(p), 0.0);
+    }
+
+    #[test]
+    fn test_detailed_balance() {
+        use {Fields, RunOptions, Stats, create_ensemble_seeded, run_ensemble};
+
+        // electrons stay in equilibrium with lattice, $\langle E \rangle = T$ in 2D parabolic band
+        let temperature = 1e-2;
+        let phonons = Phonons { temperature, ..Phonons::new(1e-2, 0.05, 0.1) };
+        let m = &Parabolic::new(10.0, square(), phonons);
+        let f = &Fields::zero();
+        let ensemble = create_ensemble_seeded(100, m, temperature, 4);
+        let options = &RunOptions::default();
+        let stats = Stats::from_ensemble(&run_ensemble(&ensemble, 1e-1, 50.0, f, options));
+        assert!((stats.energy - temperature).abs() < 0.1 * temperature, "{}", stats.energy);
+        assert!(stats.optical_absorption > 0.0);
+        // without absorption electrons lose energy
+        let m = &Parabolic::new(10.0, square(), Phonons::new(1e-2, 0.05, 0.1));
+        let ensemble = create_ensemble_seeded(100, m, temperature, 4);
+        let stats = Stats::from_ensemble(&run_ensemble(&ensemble, 1e-1, 50.0, f, options));
+        assert!(stats.energy < 0.8 * temperature);
+        assert_eq!(stats.optical_absorption, 0.0);
+    }
+
+    #[test]
+    fn test_anisotropic() {
+        let m = AnisotropicParabolic::new((10.0, 2.0), 0.4, square(), Phonons::new(5e-2, 0.1, 0.2));
+        let p = Vec2::new(0.3, -0.7);
+        assert!(gradient_error(&m, p) < 1e-8);
+        assert!((m.velocity(p) - m.energy_gradient(p)).len() < 1e-12);
+        for i in 0..16 {
+            let theta = 2.0 * PI * i as f64 / 16.0;
+            for p in m.momentums(0.1, theta) {
+                assert!((m.energy(p) - 0.1).abs() < 1e-12);
+            }
+        }
+        let vmax = m.bz.vertices().iter().map(|&v| m.energy(v)).fold(0.0, f64::max);
+        assert_eq!(m.max_energy(), vmax);
+        for &e in &[0.02, 0.05, 0.08] {
+            let dos = probability(e, &m, 1e-6);
+            let p = m.momentums(e, 1.2)[0];
+            assert!((m.acoustic_scattering(p) - 0.1 * dos / (2.0 * PI)).abs() < 1e-4,
+                    "{} {}", m.acoustic_scattering(p), 0.1 * dos / (2.0 * PI));
+        }
+    }
+}
@@ -0,0 +1,426 @@
+//! Tight-binding minibands of superlattices
+use std::f64::consts::PI;
+use material::{Material, BrillouinZone};
+use materials::{Phonons, DosTable};
+use linal::Vec2;
+
+/// Number of points in tabulated density of states
+const DOS_POINTS: usize = 128;
+/// Maximal number of iterations of root search along ray
+const MAX_ITERATIONS: usize = 100;
+
+/// Root of $A (1 - \cos \omega p) = E$ on $[0, \pi / \omega)$
+fn cosine_root(a: f64, omega: f64, energy: f64) -> Option<f64> {
+    if energy < 0.0 || energy > 2.0 * a {
+        return None;
+    }
+    Some((1.0 - energy / a).acos() / omega)
+}
+
+/// Root of function, which increases on $[0, p\_{max}]$, by Newton method safeguarded
+/// by bisection. `f` returns value of function and its derivative.
+fn increasing_root<F: Fn(f64) -> (f64, f64)>(f: F, pmax: f64) -> Option<f64> {
+    let (mut a, mut b) = (0.0, pmax);
+    let (fa, fb) = (f(a).0, f(b).0);
+    if fa > 0.0 || fb <= 0.0 {
+        return None;
+    }
+    if fa == 0.0 {
+        return Some(0.0);
+    }
+    let mut p = pmax * fa / (fa - fb);
+    for _ in 0..MAX_ITERATIONS {
+        let (y, dy) = f(p);
+        if y < 0.0 {
+            a = p;
+        } else {
+            b = p;
+        }
+        let newton = p - y / dy;
+        let next = if dy > 0.0 && newton > a && newton < b { newton } else { (a + b) / 2.0 };
+        if (next - p).abs() <= 1e-15 * pmax || b - a <= 1e-15 * pmax {
+            return Some(next);
+        }
+        p = next;
+    }
+    Some(p)
+}
+
+#[derive(Clone)]
+/// Superlattice with period `d` along x axis and free motion along y axis:
+/// $E(p) = \frac{\Delta}{2} (1 - \cos p\_x d) + \frac{p\_y\^2}{2 m}$.
+///
+/// Brillouin zone is $|p\_x| < \pi / d$, $|p\_y| <$ `py_max`, so `py_max` should be far
+/// beyond thermal momentum. Inside the zone $|p\_x d| \le \pi$, so energy grows along every
+/// ray from its center and there is at most one solution of `momentums` in every direction.
+pub struct Superlattice1D {
+    pub delta: f64,
+    pub period: f64,
+    pub mass: f64,
+    pub phonons: Phonons,
+    bz: BrillouinZone,
+    dos: DosTable,
+}
+
+impl Superlattice1D {
+    pub fn new(delta: f64, period: f64, mass: f64, py_max: f64, phonons: Phonons) -> Superlattice1D {
+        let px_max = PI / period;
+        let mut m = Superlattice1D {
+            delta,
+            period,
+            mass,
+            phonons,
+            bz: BrillouinZone::new(Vec2::new(-px_max, -py_max),
+                                   Vec2::new(px_max, -py_max),
+                                   Vec2::new(-px_max, py_max)),
+            dos: DosTable::empty(),
+        };
+        m.dos = DosTable::new(&m, DOS_POINTS);
+        m
+    }
+}
+
+impl Material for Superlattice1D {
+    fn energy(&self, p: Vec2) -> f64 {
+        self.delta / 2.0 * (1.0 - (p.x * self.period).cos()) + p.y * p.y / (2.0 * self.mass)
+    }
+    fn energy_gradient(&self, p: Vec2) -> Vec2 {
+        Vec2::new(self.delta * self.period / 2.0 * (p.x * self.period).sin(),
+                  p.y / self.mass)
+    }
+    fn min_energy(&self) -> f64 {
+        0.0
+    }
+    fn max_energy(&self) -> f64 {
+        self.bz.vertices().iter().map(|&v| self.energy(v)).fold(0.0, f64::max)
+    }
+    /// Along superlattice axis $p = \arccos(1 - 2 E / \Delta) / d \cos \theta$, across it
+    /// $p = \sqrt{2 m E}$, otherwise monotonous equation is solved numerically
+    fn momentums(&self, energy: f64, theta: f64) -> Vec<Vec2> {
+        let pmax = self.bz.pmax(theta);
+        let (sin, cos) = theta.sin_cos();
+        let (a, omega) = (self.delta / 2.0, cos.abs() * self.period);
+        let b = sin * sin / (2.0 * self.mass);
+        let root = if sin.abs() < 1e-12 {
+            cosine_root(a, omega, energy)
+        } else if cos.abs() < 1e-12 {
+            if energy >= 0.0 { Some((energy / b).sqrt()) } else { None }
+        } else {
+            increasing_root(|p| {
+                                let (s, c) = (omega * p).sin_cos();
+                                (a * (1.0 - c) + b * p * p - energy, a * omega * s + 2.0 * b * p)
+                            },
+                            pmax)
+        };
+        root.into_iter().filter(|&p| p < pmax).map(|p| Vec2::from_polar(p, theta)).collect()
+    }
+    fn brillouin_zone(&self) -> &BrillouinZone {
+        &self.bz
+    }
+    fn optical_energy(&self) -> f64 {
+        self.phonons.optical_energy
+    }
+    fn optical_scattering(&self, p: Vec2) -> f64 {
+        let e = self.energy(p) - self.optical_energy();
+        self.phonons.optical_rate(self.dos.get(e))
+    }
+    fn optical_absorption(&self, p: Vec2) -> f64 {
+        let e = self.energy(p) + self.optical_energy();
+        self.phonons.absorption_rate(self.dos.get(e))
+    }
+    fn acoustic_scattering(&self, p: Vec2) -> f64 {
+        self.phonons.acoustic_rate(self.dos.get(self.energy(p)))
+    }
+}
+
+#[derive(Clone)]
+/// Superlattice modulated along both axes with periods `period.0` and `period.1`:
+/// $E(p) = \frac{\Delta\_1}{2} (1 - \cos p\_x d\_1) + \frac{\Delta\_2}{2} (1 - \cos p\_y d\_2)$.
+/// As for `Superlattice1D`, there is at most one solution of `momentums` in every direction.
+pub struct Superlattice2D {
+    pub delta: (f64, f64),
+    pub period: (f64, f64),
+    pub phonons: Phonons,
+    bz: BrillouinZone,
+    dos: DosTable,
+}
+
+impl Superlattice2D {
+    pub fn new(delta: (f64, f64), period: (f64, f64), phonons: Phonons) -> Superlattice2D {
+        let px_max = PI / period.0;
+        let py_max = PI / period.1;
+        let mut m = Superlattice2D {
+            delta,
+            period,
+            phonons,
+            bz: BrillouinZone::new(Vec2::new(-px_max, -py_max),
+                                   Vec2::new(px_max, -py_max),
+                                   Vec2::new(-px_max, py_max)),
+            dos: DosTable::empty(),
+        };
+        m.dos = DosTable::new(&m, DOS_POINTS);
+        m
+    }
+}
+
+impl Material for Superlattice2D {
+    fn energy(&self, p: Vec2) -> f64 {
+        self.delta.0 / 2.0 * (1.0 - (p.x * self.period.0).cos()) +
+        self.delta.1 / 2.0 * (1.0 - (p.y * self.period.1).cos())
+    }
+    fn energy_gradient(&self, p: Vec2) -> Vec2 {
+        Vec2::new(self.delta.0 * self.period.0 / 2.0 * (p.x * self.period.0).sin(),
+                  self.delta.1 * self.period.1 / 2.0 * (p.y * self.period.1).sin())
+    }
+    fn min_energy(&self) -> f64 {
+        0.0
+    }
+    fn max_energy(&self) -> f64 {
+        self.delta.0 + self.delta.1
+    }
+    /// Along axes and in directions with equal frequencies $d\_1 |\cos \theta| = d\_2 |\sin \theta|$
+    /// energy is a single cosine of $p$ and is inverted by $\arccos$, otherwise monotonous
+    /// equation is solved numerically
+    fn momentums(&self, energy: f64, theta: f64) -> Vec<Vec2> {
+        let pmax = self.bz.pmax(theta);
+        let (sin, cos) = theta.sin_cos();
+        let (a1, a2) = (self.delta.0 / 2.0, self.delta.1 / 2.0);
+        let (w1, w2) = (cos.abs() * self.period.0, sin.abs() * self.period.1);
+        let scale = w1 + w2;
+        let root = if w2 < 1e-12 * scale {
+            cosine_root(a1, w1, energy)
+        } else if w1 < 1e-12 * scale {
+            cosine_root(a2, w2, energy)
+        } else if (w1 - w2).abs() < 1e-12 * scale {
+            cosine_root(a1 + a2, w1, energy)
+        } else {
+            increasing_root(|p| {
+                                let (s1, c1) = (w1 * p).sin_cos();
+                                let (s2, c2) = (w2 * p).sin_cos();
+                                (a1 * (1.0 - c1) + a2 * (1.0 - c2) - energy,
+                                 a1 * w1 * s1 + a2 * w2 * s2)
+                            },
+                            pmax)
+        };
+        root.into_iter().filter(|&p| p < pmax).map(|p| Vec2::from_polar(p, theta)).collect()
+    }
+    fn brillouin_zone(&self) -> &BrillouinZone {
+        &self.bz
+    }
+    fn optical_energy(&self) -> f64 {
+        self.phonons.optical_energy
+    }
+    fn optical_scattering(&self, p: Vec2) -> f64 {
+        let e = self.energy(p) - self.optical_energy();
+        self.phonons.optical_rate(self.dos.get(e))
+    }
+    fn optical_absorption(&self, p: Vec2) -> f64 {
+        let e = self.energy(p) + self.optical_energy();
+        self.phonons.absorption_rate(self.dos.get(e))
+    }
+    fn acoustic_scattering(&self, p: Vec2) -> f64 {
+        self.phonons.acoustic_rate(self.dos.get(self.energy(p)))
+    }
+}
+
+/// Modified Bessel function of the first kind $I\_n(x)$
+fn bessel_i(n: i32, x: f64) -> f64 {
+    let mut term = (x / 2.0).powi(n) / (1..n + 1).fold(1.0, |acc, k| acc * k as f64);
+    let mut sum = term;
+    let mut k = 1;
+    while term > 1e-17 * sum {
+        term *= x * x / 4.0 / (k as f64 * (k + n) as f64);
+        sum += term;
+        k += 1;
+    }
+    sum
+}
+
+/// Esaki–Tsu drift velocity along axis of `Superlattice1D` in constant electric field `e`
+/// (directed along superlattice axis) in relaxation time approximation:
+/// $v = -\frac{\Delta d}{2} \frac{I\_1(\Delta / 2 T)}{I\_0(\Delta / 2 T)} \frac{\Omega \tau}{1 + \Omega\^2 \tau\^2}$,
+/// where $\Omega = e d$ is Bloch frequency.
+pub fn esaki_tsu(delta: f64, period: f64, e: f64, tau: f64, temperature: f64) -> f64 {
+    let x = delta / (2.0 * temperature);
+    let wt = e * period * tau;
+    -delta * period / 2.0 * bessel_i(1, x) / bessel_i(0, x) * wt / (1.0 + wt * wt)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bessel() {
+        assert!((bessel_i(0, 1.0) - 1.266_065_877_752_008_4).abs() < 1e-14);
+        assert!((bessel_i(1, 1.0) - 0.565_159_103_992_485).abs() < 1e-14);
+        assert!((bessel_i(1, 5.0) - 24.335_642_142_450_524).abs() < 1e-11);
+    }
+
+    #[test]
+    fn test_superlattice_1d() {
+        let m = Superlattice1D::new(1.0, 2.0, 0.5, 3.0, Phonons::new(0.2, 0.1, 0.1));
+        assert!((m.max_energy() - 10.0).abs() < 1e-12);
+        let h = 1e-6;
+        let p = Vec2::new(0.4, -0.9);
+        let fd = Vec2::new(m.energy(p + Vec2::new(h, 0.0)) - m.energy(p - Vec2::new(h, 0.0)),
+                           m.energy(p + Vec2::new(0.0, h)) - m.energy(p - Vec2::new(0.0, h))) /
+                 (2.0 * h);
+        assert!((fd - m.energy_gradient(p)).len() < 1e-8);
+        for i in 0..32 {
+            let theta = 2.0 * PI * i as f64 / 32.0;
+            for &e in &[0.3, 1.5] {
+                let ps = m.momentums(e, theta);
+                assert!(ps.len() <= 1);
+                for p in ps {
+                    assert!((m.energy(p) - e).abs() < 1e-10);
+                }
+            }
+            assert_eq!(m.momentums(0.3, theta).len(), 1);
+        }
+        assert!(m.momentums(1.5, 0.0).is_empty());
+        assert_eq!(m.momentums(1.5, PI / 2.0).len(), 1);
+        // acoustic rate of parabolic band with mass $\sqrt{m\_x m\_y}$ near bottom of band
+        let mx = 2.0 / (m.delta * m.period * m.period);
+        let p = m.momentums(1e-3, 0.0)[0];
+        let expected = 0.1 * 2.0 * PI * (mx * m.mass).sqrt() / (2.0 * PI);
+        assert!((m.acoustic_scattering(p) - expected).abs() < 1e-2 * expected);
+        assert_eq!(m.optical_scattering(p), 0.0);
+    }
+
+    #[test]
+    fn test_superlattice_2d() {
+        let m = Superlattice2D::new((1.0, 0.5), (1.0, 2.0), Phonons::new(0.2, 0.1, 0.1));
+        assert!((m.max_energy() - 1.5).abs() < 1e-12);
+        for i in 0..32 {
+            let theta = 2.0 * PI * i as f64 / 32.0;
+            for &e in &[0.3, 1.2] {
+                for p in m.momentums(e, theta) {
+                    assert!((m.energy(p) - e).abs() < 1e-10);
+                }
+            }
+        }
+        assert_eq!(m.momentums(0.3, 0.0).len(), 1);
+        assert!(m.momentums(1.2, 0.0).is_empty());
+    }
+
+    #[test]
+    fn test_closed_form_momentums() {
+        use material::ray_roots;
+
+        let m1 = Superlattice1D::new(1.0, 2.0, 0.5, 3.0, Phonons::new(0.2, 0.1, 0.1));
+        let m2 = Superlattice2D::new((1.0, 0.5), (1.0, 2.0), Phonons::new(0.2, 0.1, 0.1));
+        // inversion of cosine along axes and diagonal of equal frequencies
+        let p = m1.momentums(0.5, PI)[0];
+        assert!((p - Vec2::new(-PI / 4.0, 0.0)).len() < 1e-12);
+        let p = m2.momentums(0.25, PI / 2.0)[0];
+        assert!((p - Vec2::new(0.0, PI / 4.0)).len() < 1e-12);
+        let p = m2.momentums(0.75, 0.5f64.atan())[0];
+        assert!((p.x - PI / 2.0).abs() < 1e-12 && (m2.energy(p) - 0.75).abs() < 1e-12);
+        // the same roots as general root finder
+        for i in 0..64 {
+            let theta = 2.0 * PI * (i as f64 + 0.3) / 64.0;
+            let n = Vec2::from_polar(1.0, theta);
+            for &e in &[0.05, 0.4, 0.9, 1.4] {
+                let pmax = m1.brillouin_zone().pmax(theta);
+                let expected = ray_roots(|p| m1.energy_polar(p, theta) - e,
+                                         |p| m1.energy_gradient(n * p).dot(n),
+                                         pmax,
+                                         64);
+                let ps = m1.momentums(e, theta);
+                assert_eq!(ps.len(), expected.len());
+                for (p, r) in ps.iter().zip(&expected) {
+                    assert!((p.len() - r).abs() < 1e-10);
+                }
+                let pmax = m2.brillouin_zone().pmax(theta);
+                let expected = ray_roots(|p| m2.energy_polar(p, theta) - e,
+                                         |p| m2.energy_gradient(n * p).dot(n),
+                                         pmax,
+                                         64);
+                let ps = m2.momentums(e, theta);
+                assert_eq!(ps.len(), expected.len());
+                for (p, r) in ps.iter().zip(&expected) {
+                    assert!((p.len() - r).abs() < 1e-10);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_esaki_tsu_limits() {
+        // maximum at $\Omega \tau = 1$
+        let v = esaki_tsu(1.0, 1.0, 1.0, 1.0, 1e-3);
+        assert!((v + 0.25).abs() < 1e-3);
+        assert!(esaki_tsu(1.0, 1.0, 1.0, 1.1, 1e-3) > v);
+        assert!(esaki_tsu(1.0, 1.0, 1.0, 0.9, 1e-3) > v);
+        assert!(esaki_tsu(1.0, 1.0, -1.0, 1.0, 1.0) > 0.0);
+    }
+
+    use scattering::ScatteringMechanism;
+    use material::MultiBand;
+
+    /// Relaxation to equilibrium with probability $1 / \tau$, final state is drawn from
+    /// Boltzmann distribution regardless of initial one as in relaxation time approximation
+    struct Relaxation {
+        tau: f64,
+        temperature: f64,
+    }
+
+    impl ScatteringMechanism for Relaxation {
+        fn name(&self) -> &str {
+            "relaxation"
+        }
+        fn rate(&self, _m: &dyn MultiBand, _from: usize, _to: usize, _p: Vec2) -> f64 {
+            1.0 / self.tau
+        }
+        fn energy_change(&self, _m: &dyn MultiBand, _from: usize, _to: usize) -> f64 {
+            0.0
+        }
+        fn final_state(&self,
+                       m: &dyn MultiBand,
+                       _from: usize,
+                       to: usize,
+                       _energy: f64,
+                       _p: Vec2,
+                       uniform: &mut dyn FnMut() -> f64)
+                       -> Option<Vec2> {
+            loop {
+                let q = m.zone().sample(uniform(), uniform());
+                let e = m.band_energy(to, q) - m.band_min_energy(to);
+                if uniform() < (-e / self.temperature).exp() {
+                    return Some(q);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_esaki_tsu_benchmark() {
+        use stats::{Mean, MeanStd};
+        use test_support::with_mechanisms;
+        use {Fields, RunOptions, create_ensemble_seeded, run_ensemble};
+
+        // maximum of drift velocity at $\Omega \tau = 1$
+        let (e, temperature, tau) = (0.5, 0.3, 2.0);
+        // electrons relax to equilibrium with time $\tau$
+        let superlattice = Superlattice1D::new(1.0, 1.0, 1.0, 6.0, Phonons::new(0.0, 0.0, 0.0));
+        let m = &with_mechanisms(superlattice, move || vec![Box::new(Relaxation { tau, temperature })]);
+        let f = &Fields::new((Vec2::new(e, 0.0), Vec2::zero(), Vec2::zero()),
+                             (0.0, 0.0, 0.0),
+                             (0.0, 0.0),
+                             0.0);
+        let ensemble = create_ensemble_seeded(2000, m, temperature, 1);
+        let summaries = run_ensemble(&ensemble, 2e-2, 10.0 * tau, f, &RunOptions::default());
+        // transient is discarded, velocity is averaged over the second half of run
+        let velocity: Vec<f64> = summaries.iter()
+            .map(|s| {
+                let (start, end) = (s.trajectory[31], s.trajectory[63]);
+                (end.1.x - start.1.x) / (end.0 - start.0)
+            })
+            .collect();
+        let (mean, std) = (velocity.mean(), velocity.mean_std());
+        let expected = esaki_tsu(1.0, 1.0, e, tau, temperature);
+        assert!((mean - expected).abs() < 3.0 * std, "{} != {} ± {}", expected, mean, std);
+        assert!(std < 0.05 * expected.abs());
+    }
+}
@@ -0,0 +1,281 @@
+//! Band structure tabulated on regular grid in brillouin zone
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use material::{Material, BrillouinZone};
+use materials::{Phonons, DosTable};
+use error::LoadError;
+use linal::Vec2;
+
+/// Number of points in tabulated density of states
+const DOS_POINTS: usize = 128;
+/// Refinement of grid used to find energy range and density of states
+const REFINEMENT: usize = 4;
+
+/// Weights of periodic cubic convolution (Catmull-Rom) interpolation and their
+/// derivatives for offset `t` from node
+fn cubic_weights(t: f64) -> ([f64; 4], [f64; 4]) {
+    let t2 = t * t;
+    let t3 = t2 * t;
+    ([(-t3 + 2.0 * t2 - t) / 2.0,
+      (3.0 * t3 - 5.0 * t2 + 2.0) / 2.0,
+      (-3.0 * t3 + 4.0 * t2 + t) / 2.0,
+      (t3 - t2) / 2.0],
+     [(-3.0 * t2 + 4.0 * t - 1.0) / 2.0,
+      (9.0 * t2 - 10.0 * t) / 2.0,
+      (-9.0 * t2 + 8.0 * t + 1.0) / 2.0,
+      (3.0 * t2 - 2.0 * t) / 2.0])
+}
+
+#[derive(Clone)]
+/// Band with energies given on $n\_1 \times n\_2$ grid $p\_{ij} = b\_1 i / n\_1 + b\_2 j / n\_2$,
+/// where $b\_1, b\_2$ is basis of brillouin zone. Energy is interpolated by periodic
+/// bicubic convolution, so it and its gradient are continuous.
+pub struct Tabulated {
+    pub phonons: Phonons,
+    energies: Vec<Vec<f64>>,
+    bz: BrillouinZone,
+    min_energy: f64,
+    max_energy: f64,
+    dos: DosTable,
+}
+
+impl Tabulated {
+    /// Creates band from energies `energies[i][j]` at $p\_{ij}$. Grid must be at least
+    /// $2 \times 2$, all rows of the same length and energies finite and not all equal.
+    pub fn new(bz: BrillouinZone,
+               energies: Vec<Vec<f64>>,
+               phonons: Phonons)
+               -> Result<Tabulated, LoadError> {
+        let (n1, n2) = (energies.len(), energies.first().map_or(0, |row| row.len()));
+        if n1 < 2 || n2 < 2 {
+            return Err(LoadError::Invalid(format!("grid {}x{} is too small", n1, n2)));
+        }
+        if let Some(i) = energies.iter().position(|row| row.len() != n2) {
+            return Err(LoadError::Invalid(format!("row {} has {} energies, {} expected",
+                                                  i,
+                                                  energies[i].len(),
+                                                  n2)));
+        }
+        if energies.iter().any(|row| row.iter().any(|e| !e.is_finite())) {
+            return Err(LoadError::Invalid("energies are not finite".to_string()));
+        }
+        if energies.iter().all(|row| row.iter().all(|&e| e == energies[0][0])) {
+            return Err(LoadError::Invalid("band is flat".to_string()));
+        }
+        let mut m = Tabulated {
+            phonons,
+            energies,
+            bz,
+            min_energy: 0.0,
+            max_energy: 0.0,
+            dos: DosTable::empty(),
+        };
+        let (n1, n2) = m.size();
+        let (b1, b2) = m.bz.basis();
+        let (k1, k2) = (n1 * REFINEMENT, n2 * REFINEMENT);
+        let fine: Vec<Vec<f64>> = (0..k1)
+            .map(|i| {
+                (0..k2)
+                    .map(|j| m.energy(b1 * (i as f64 / k1 as f64) + b2 * (j as f64 / k2 as f64)))
+                    .collect()
+            })
+            .collect();
+        m.dos = DosTable::from_grid(b1.area(b2).abs(), &fine, DOS_POINTS);
+        m.min_energy = m.dos.min;
+        m.max_energy = m.dos.min + m.dos.step * DOS_POINTS as f64;
+        Ok(m)
+    }
+
+    /// Reads band from text file, see `Tabulated::parse` for format
+    pub fn load<P: AsRef<Path>>(path: P, phonons: Phonons) -> Result<Tabulated, LoadError> {
+        let mut s = String::new();
+        File::open(path)?.read_to_string(&mut s)?;
+        Tabulated::parse(&s, phonons)
+    }
+
+    /// Parses band from text. Text contains whitespace separated numbers: sizes of grid
+    /// $n\_1$ and $n\_2$, components of basis vectors $b\_1$ and $b\_2$ and $n\_1 n\_2$ energies
+    /// with $j$ changing fastest. Everything after `#` till the end of line is ignored.
+    /// Brillouin zone is Wigner–Seitz cell of basis.
+    pub fn parse(s: &str, phonons: Phonons) -> Result<Tabulated, LoadError> {
+        let mut tokens = s.lines()
+            .map(|line| line.split('#').next().unwrap_or(""))
+            .flat_map(|line| line.split_whitespace());
+        let mut next = |what: &str| -> Result<f64, LoadError> {
+            let token = tokens.next()
+                .ok_or_else(|| LoadError::Parse(format!("unexpected end of table, {} expected", what)))?;
+            token.parse::<f64>()
+                .map_err(|_| LoadError::Parse(format!("invalid {}: {}", what, token)))
+        };
+        let n1 = grid_size(next("grid size")?)?;
+        let n2 = grid_size(next("grid size")?)?;
+        let b1 = Vec2::new(next("basis vector")?, next("basis vector")?);
+        let b2 = Vec2::new(next("basis vector")?, next("basis vector")?);
+        // rows are not allocated in advance, so absurd size fails at the end of text
+        let energies = (0..n1)
+            .map(|_| (0..n2).map(|_| next("energy")).collect())
+            .collect::<Result<Vec<Vec<f64>>, LoadError>>()?;
+        Tabulated::new(BrillouinZone::wigner_seitz(b1, b2), energies, phonons)
+    }
+
+    fn size(&self) -> (usize, usize) {
+        (self.energies.len(), self.energies[0].len())
+    }
+
+    /// Interpolated energy and its derivatives by grid coordinates
+    fn interpolate(&self, p: Vec2) -> (f64, f64, f64) {
+        let (n1, n2) = self.size();
+        let s = p.dot(self.bz.dual_basis().0) * n1 as f64;
+        let t = p.dot(self.bz.dual_basis().1) * n2 as f64;
+        let (i, j) = (s.floor(), t.floor());
+        let (ws, dws) = cubic_weights(s - i);
+        let (wt, dwt) = cubic_weights(t - j);
+        let (i, j) = (i as i64, j as i64);
+        let mut res = (0.0, 0.0, 0.0);
+        for a in 0..4 {
+            let row = &self.energies[(i + a as i64 - 1).rem_euclid(n1 as i64) as usize];
+            for b in 0..4 {
+                let e = row[(j + b as i64 - 1).rem_euclid(n2 as i64) as usize];
+                res.0 += ws[a] * wt[b] * e;
+                res.1 += dws[a] * wt[b] * e;
+                res.2 += ws[a] * dwt[b] * e;
+            }
+        }
+        res
+    }
+}
+
+/// Checks that number read from table is valid size of grid
+fn grid_size(n: f64) -> Result<usize, LoadError> {
+    if n >= 2.0 && n.fract() == 0.0 && n <= u32::MAX as f64 {
+        Ok(n as usize)
+    } else {
+        Err(LoadError::Parse(format!("invalid grid size: {}", n)))
+    }
+}
+
+impl Material for Tabulated {
+    fn energy(&self, p: Vec2) -> f64 {
+        self.interpolate(p).0
+    }
+    fn energy_gradient(&self, p: Vec2) -> Vec2 {
+        let (n1, n2) = self.size();
+        let (_, ds, dt) = self.interpolate(p);
+        self.bz.dual_basis().0 * (ds * n1 as f64) + self.bz.dual_basis().1 * (dt * n2 as f64)
+    }
+    fn min_energy(&self) -> f64 {
+        self.min_energy
+    }
+    fn max_energy(&self) -> f64 {
+        self.max_energy
+    }
+    fn brillouin_zone(&self) -> &BrillouinZone {
+        &self.bz
+    }
+    fn optical_energy(&self) -> f64 {
+        self.phonons.optical_energy
+    }
+    fn optical_scattering(&self, p: Vec2) -> f64 {
+        let e = self.energy(p) - self.optical_energy();
+        self.phonons.optical_rate(self.dos.get(e))
+    }
+    fn optical_absorption(&self, p: Vec2) -> f64 {
+        let e = self.energy(p) + self.optical_energy();
+        self.phonons.absorption_rate(self.dos.get(e))
+    }
+    fn acoustic_scattering(&self, p: Vec2) -> f64 {
+        self.phonons.acoustic_rate(self.dos.get(self.energy(p)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::PI;
+    use materials::Superlattice2D;
+    use validation::check;
+
+    fn cosine() -> Superlattice2D {
+        Superlattice2D::new((1.0, 0.5), (1.0, 2.0), Phonons::new(0.2, 0.1, 0.1))
+    }
+
+    fn table(exact: &Superlattice2D, n: usize) -> Tabulated {
+        let (b1, b2) = exact.brillouin_zone().basis();
+        let energies = (0..n)
+            .map(|i| {
+                (0..n)
+                    .map(|j| exact.energy(b1 * (i as f64 / n as f64) + b2 * (j as f64 / n as f64)))
+                    .collect()
+            })
+            .collect();
+        Tabulated::new(exact.brillouin_zone().clone(), energies, Phonons::new(0.2, 0.1, 0.1))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_interpolation() {
+        let exact = cosine();
+        let m = table(&exact, 32);
+        for &p in &[Vec2::new(0.3, -0.7), Vec2::new(-2.9, 1.4), Vec2::new(1.7, 0.05)] {
+            assert!((m.energy(p) - exact.energy(p)).abs() < 1e-3);
+            assert!((m.energy_gradient(p) - exact.energy_gradient(p)).len() < 1e-2);
+        }
+        // nodes are reproduced exactly
+        let p = m.bz.basis().0 * (3.0 / 32.0) + m.bz.basis().1 * (5.0 / 32.0);
+        assert!((m.energy(p) - exact.energy(p)).abs() < 1e-12);
+        assert!((m.min_energy() - 0.0).abs() < 1e-12);
+        assert!((m.max_energy() - 1.5).abs() < 1e-3);
+        for p in m.momentums(0.7, 1.0) {
+            assert!((m.energy(p) - 0.7).abs() < 1e-10);
+        }
+        assert!(check(&m, 16, 1e-3).is_ok());
+        for &e in &[0.2, 0.7, 1.3] {
+            let p = m.momentums(e, 0.3)[0];
+            let expected = exact.acoustic_scattering(exact.momentums(e, 0.3)[0]);
+            assert!((m.acoustic_scattering(p) - expected).abs() < 2e-2 * expected);
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        let text = "# cosine band\n\
+                    4 4\n\
+                    6.283185307179586 0  # b1\n\
+                    0 6.283185307179586  # b2\n\
+                    0 1 2 1\n\
+                    1 2 3 2\n\
+                    2 3 4 3\n\
+                    1 2 3 2\n";
+        let m = Tabulated::parse(text, Phonons::new(0.2, 0.1, 0.1)).unwrap();
+        assert_eq!(m.size(), (4, 4));
+        assert!((m.energy(Vec2::new(PI / 2.0, PI)) - 3.0).abs() < 1e-12);
+        assert!((m.energy(Vec2::new(-PI / 2.0, 0.0)) - 1.0).abs() < 1e-12);
+        assert_eq!(m.min_energy(), 0.0);
+        assert_eq!(m.max_energy(), 4.0);
+
+        assert!(Tabulated::parse("4 4 1 0 0 1 0 1", Phonons::new(0.2, 0.1, 0.1)).is_err());
+        assert!(Tabulated::parse("4 x", Phonons::new(0.2, 0.1, 0.1)).is_err());
+        assert!(Tabulated::load("/nonexistent", Phonons::new(0.2, 0.1, 0.1)).is_err());
+        for text in &["-4 4", "4.5 4", "NaN 4", "1 4", "1e30 4"] {
+            match Tabulated::parse(text, Phonons::new(0.2, 0.1, 0.1)) {
+                Err(LoadError::Parse(_)) => {}
+                _ => panic!("size is accepted: {}", text),
+            }
+        }
+    }
+
+    #[test]
+    fn test_invalid_grid() {
+        let bz = cosine().brillouin_zone().clone();
+        let phonons = Phonons::new(0.2, 0.1, 0.1);
+        assert!(Tabulated::new(bz.clone(), vec![], phonons.clone()).is_err());
+        assert!(Tabulated::new(bz.clone(), vec![vec![], vec![]], phonons.clone()).is_err());
+        assert!(Tabulated::new(bz.clone(), vec![vec![0.0, 1.0], vec![1.0]], phonons.clone()).is_err());
+        let nan = vec![vec![0.0, 1.0], vec![1.0, f64::NAN]];
+        assert!(Tabulated::new(bz.clone(), nan, phonons.clone()).is_err());
+        let flat = vec![vec![1.0, 1.0], vec![1.0, 1.0]];
+        assert!(Tabulated::new(bz.clone(), flat, phonons.clone()).is_err());
+        assert!(Tabulated::new(bz, vec![vec![0.0, 1.0], vec![1.0, 2.0]], phonons).is_ok());
+    }
+}
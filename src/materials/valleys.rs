@@ -0,0 +1,200 @@
+//! Several valleys coupled by intervalley phonon scattering
+use std::f64::consts::PI;
+use material::{Material, MultiBand, BrillouinZone};
+use materials::DosTable;
+use linal::Vec2;
+
+/// Number of points in tabulated density of states
+const DOS_POINTS: usize = 128;
+
+#[derive(Clone)]
+/// Parameters of intervalley phonon scattering, probability is
+/// $W = 2 \pi |M|\^2 \int \delta(E'(p') - E + \hbar \omega\_{iv}) d\^2 p' / (2 \pi)\^2$
+pub struct IntervalleyPhonons {
+    /// intervalley phonon energy $\hbar \omega\_{iv}$
+    pub energy: f64,
+    /// $D\_{iv}\^2 / 2 \rho \omega\_{iv}$
+    pub coupling: f64,
+}
+
+impl IntervalleyPhonons {
+    pub fn new(energy: f64, coupling: f64) -> IntervalleyPhonons {
+        IntervalleyPhonons { energy, coupling }
+    }
+
+    /// Intervalley scattering probability for given density of final states
+    pub fn rate(&self, dos: f64) -> f64 {
+        self.coupling * dos / (2.0 * PI)
+    }
+}
+
+#[derive(Clone)]
+/// Material with one band in every valley. Momentum in each valley is measured from
+/// its center, zone of the first valley is used for all of them. Scattering inside
+/// valley is given by valleys themselves, electron moves to any other valley by
+/// emission of intervalley phonon.
+pub struct Valleys<T: Material> {
+    pub valleys: Vec<T>,
+    pub intervalley: IntervalleyPhonons,
+    dos: Vec<DosTable>,
+}
+
+impl<T: Material> Valleys<T> {
+    /// Panics if there are no valleys
+    pub fn new(valleys: Vec<T>, intervalley: IntervalleyPhonons) -> Valleys<T> {
+        assert!(!valleys.is_empty(), "there are no valleys");
+        let dos = valleys.iter().map(|v| DosTable::new(v, DOS_POINTS)).collect();
+        Valleys {
+            valleys,
+            intervalley,
+            dos,
+        }
+    }
+}
+
+impl<T: Material> MultiBand for Valleys<T> {
+    fn bands(&self) -> usize {
+        self.valleys.len()
+    }
+    fn band_energy(&self, band: usize, p: Vec2) -> f64 {
+        self.valleys[band].energy(p)
+    }
+    fn band_energy_gradient(&self, band: usize, p: Vec2) -> Vec2 {
+        self.valleys[band].energy_gradient(p)
+    }
+    fn band_velocity(&self, band: usize, p: Vec2) -> Vec2 {
+        self.valleys[band].velocity(p)
+    }
+    fn band_min_energy(&self, band: usize) -> f64 {
+        self.valleys[band].min_energy()
+    }
+    fn band_max_energy(&self, band: usize) -> f64 {
+        self.valleys[band].max_energy()
+    }
+    fn band_momentums(&self, band: usize, energy: f64, theta: f64) -> Vec<Vec2> {
+        self.valleys[band].momentums(energy, theta)
+    }
+    fn zone(&self) -> &BrillouinZone {
+        self.valleys[0].brillouin_zone()
+    }
+    fn band_optical_energy(&self, from: usize, _to: usize) -> f64 {
+        self.valleys[from].optical_energy()
+    }
+    fn band_optical_scattering(&self, from: usize, to: usize, p: Vec2) -> f64 {
+        if from == to { self.valleys[from].optical_scattering(p) } else { 0.0 }
+    }
+    fn band_optical_absorption(&self, from: usize, to: usize, p: Vec2) -> f64 {
+        if from == to { self.valleys[from].optical_absorption(p) } else { 0.0 }
+    }
+    fn band_acoustic_scattering(&self, from: usize, to: usize, p: Vec2) -> f64 {
+        if from == to { self.valleys[from].acoustic_scattering(p) } else { 0.0 }
+    }
+    fn valleys(&self) -> usize {
+        self.valleys.len()
+    }
+    fn valley(&self, band: usize) -> usize {
+        band
+    }
+    fn intervalley_energy(&self, _from: usize, _to: usize) -> f64 {
+        self.intervalley.energy
+    }
+    fn intervalley_scattering(&self, from: usize, to: usize, p: Vec2) -> f64 {
+        if from == to {
+            return 0.0;
+        }
+        let e = self.valleys[from].energy(p) - self.intervalley.energy;
+        self.intervalley.rate(self.dos[to].get(e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use materials::{AnisotropicParabolic, Dirac, Phonons, Valley};
+    use probability::probability;
+    use {Fields, RunOptions, Stats, create_ensemble_seeded, run_ensemble};
+
+    fn graphene(coupling: f64) -> Valleys<Dirac> {
+        let phonons = Phonons::new(0.2, 0.5, 0.5);
+        Valleys::new(vec![Dirac::new(1.0, 0.1, 0.05, 1.0, Valley::K, phonons.clone()),
+                          Dirac::new(1.0, 0.1, 0.05, 1.0, Valley::KPrime, phonons)],
+                     IntervalleyPhonons::new(0.05, coupling))
+    }
+
+    #[test]
+    fn test_intervalley_rates() {
+        let m = graphene(1.0);
+        assert_eq!(m.valleys(), 2);
+        assert_eq!(m.valley(1), 1);
+        let p = m.band_momentums(0, 0.5, 0.3)[0];
+        let dos = probability(0.45, &m.valleys[1], 1e-6);
+        assert!((m.intervalley_scattering(0, 1, p) - dos / (2.0 * PI)).abs() < 1e-3);
+        assert_eq!(m.intervalley_scattering(0, 0, p), 0.0);
+        assert_eq!(m.band_acoustic_scattering(0, 1, p), 0.0);
+        // below gap plus phonon energy
+        let p = m.band_momentums(0, 0.12, 0.3)[0];
+        assert_eq!(m.intervalley_scattering(1, 0, p), 0.0);
+    }
+
+    #[test]
+    #[should_panic(expected = "there are no valleys")]
+    fn test_no_valleys() {
+        Valleys::<Dirac>::new(vec![], IntervalleyPhonons::new(0.05, 1.0));
+    }
+
+    #[test]
+    fn test_valley_occupation() {
+        let f = &Fields::new((Vec2::new(0.05, 0.0), Vec2::zero(), Vec2::zero()),
+                             (0.0, 0.0, 0.0),
+                             (0.0, 0.0),
+                             0.0);
+        let m = &graphene(1.0);
+        let stats = Stats::from_ensemble(&run_ensemble(&create_ensemble_seeded(40, m, 0.1, 5),
+                                                       1e-2,
+                                                       5.0,
+                                                       f,
+                                                       &RunOptions::default()));
+        assert!(stats.intervalley > 0.0);
+        assert_eq!(stats.interband, 0.0);
+        assert_eq!(stats.valley_occupation.len(), 2);
+        assert!((stats.valley_occupation.iter().sum::<f64>() - 1.0).abs() < 1e-9);
+        let current = stats.valley_current[0] + stats.valley_current[1];
+        assert!((current - stats.current).len() < 1e-9);
+
+        let m = &graphene(0.0);
+        let stats = Stats::from_ensemble(&run_ensemble(&create_ensemble_seeded(40, m, 0.1, 5),
+                                                       1e-2,
+                                                       5.0,
+                                                       f,
+                                                       &RunOptions::default()));
+        assert_eq!(stats.intervalley, 0.0);
+    }
+
+    #[test]
+    fn test_anisotropic_valleys() {
+        // valleys with light mass along and across field
+        let bz = BrillouinZone::new(Vec2::new(-1.0, -1.0),
+                                    Vec2::new(1.0, -1.0),
+                                    Vec2::new(-1.0, 1.0));
+        let phonons = Phonons::new(5e-2, 0.3, 0.3);
+        let light = AnisotropicParabolic::new((5.0, 20.0), 0.0, bz.clone(), phonons.clone());
+        let heavy = AnisotropicParabolic::new((5.0, 20.0), PI / 2.0, bz, phonons);
+        let m = &Valleys::new(vec![heavy, light], IntervalleyPhonons::new(1e-3, 0.5));
+        let f = &Fields::new((Vec2::new(0.05, 0.0), Vec2::zero(), Vec2::zero()),
+                             (0.0, 0.0, 0.0),
+                             (0.0, 0.0),
+                             0.0);
+        let stats = Stats::from_ensemble(&run_ensemble(&create_ensemble_seeded(100, m, 7e-3, 11),
+                                                       1e-1,
+                                                       40.0,
+                                                       f,
+                                                       &RunOptions::default()));
+        // electrons move against field, drift velocity in light valley is larger
+        let drift: Vec<f64> = (0..2)
+            .map(|i| stats.valley_current[i].x / stats.valley_occupation[i])
+            .collect();
+        assert!(drift[1] < drift[0] && drift[0] < 0.0, "{:?}", drift);
+        // hot electrons of light valley are transferred to heavy one
+        assert!(stats.valley_occupation[0] > 0.6);
+    }
+}
@@ -0,0 +1,523 @@
+//! Tight-binding bands from Wannier90 real-space hamiltonian
+use std::cell::Cell;
+use std::f64::consts::PI;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use material::{Material, BrillouinZone};
+use materials::{Phonons, DosTable, Tabulated};
+use error::LoadError;
+use linal::Vec2;
+
+/// Number of points in tabulated density of states
+const DOS_POINTS: usize = 128;
+/// Size of grid used to find energy range, density of states and approximate isoenergy points
+const GRID_POINTS: usize = 96;
+/// Maximum number of Newton iterations refining isoenergy point
+const NEWTON_ITERATIONS: usize = 16;
+
+/// Source of identifiers of hamiltonians, which are keys of cached solutions
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// Identifier of hamiltonian, band, momentum, energy and velocity
+type Solution = (usize, usize, Vec2, f64, Vec2);
+
+thread_local! {
+    /// The last solution in thread
+    static LAST: Cell<Option<Solution>> = const { Cell::new(None) };
+}
+
+#[derive(Clone)]
+/// Hopping matrix $H\_{mn}(R)$ to lattice vector $R$
+struct Hopping {
+    r: Vec2,
+    re: Vec<f64>,
+    im: Vec<f64>,
+}
+
+#[derive(Clone)]
+/// Band `band` (counting from the lowest one) of tight-binding hamiltonian
+/// $H(p) = \sum\_R e\^{i p R} H(R)$ read from Wannier90 `_hr.dat` file.
+///
+/// Hoppings along third lattice vector are summed, so the band is the cut of
+/// three-dimensional band by plane $p\_z = 0$. Velocity is found by Hellmann–Feynman
+/// theorem $v = \langle \psi | \partial H / \partial p | \psi \rangle$, it is ill-defined
+/// in points of band crossing.
+///
+/// Energy and velocity are found together by diagonalization of hamiltonian, the last of them
+/// is cached in every thread, as rates, energy and velocity are requested at the same momentum.
+/// Isoenergy points are found in band interpolated between solutions on $96 \times 96$ grid and
+/// refined by Newton iterations, so `momentums` needs few diagonalizations.
+pub struct Wannier {
+    pub band: usize,
+    pub phonons: Phonons,
+    id: usize,
+    size: usize,
+    hoppings: Vec<Hopping>,
+    bz: BrillouinZone,
+    dos: DosTable,
+    /// band interpolated on grid, absent for flat band
+    table: Option<Tabulated>,
+}
+
+impl Wannier {
+    /// Reads band from `_hr.dat` file, `lattice` are in-plane lattice vectors
+    pub fn load<P: AsRef<Path>>(path: P,
+                                lattice: (Vec2, Vec2),
+                                band: usize,
+                                phonons: Phonons)
+                                -> Result<Wannier, LoadError> {
+        let mut s = String::new();
+        File::open(path)?.read_to_string(&mut s)?;
+        Wannier::parse(&s, lattice, band, phonons)
+    }
+
+    /// Parses contents of `_hr.dat` file: comment line, number of Wannier functions,
+    /// number of lattice vectors $R$, their degeneracies and lines
+    /// `R1 R2 R3 m n Re(H) Im(H)`
+    pub fn parse(s: &str,
+                 lattice: (Vec2, Vec2),
+                 band: usize,
+                 phonons: Phonons)
+                 -> Result<Wannier, LoadError> {
+        let mut tokens = s.lines().skip(1).flat_map(|line| line.split_whitespace());
+        let mut next = |what: &str| -> Result<f64, LoadError> {
+            let token = tokens.next()
+                .ok_or_else(|| LoadError::Parse(format!("unexpected end of file, {} expected", what)))?;
+            token.parse::<f64>()
+                .map_err(|_| LoadError::Parse(format!("invalid {}: {}", what, token)))
+        };
+        let size = integer(next("number of Wannier functions")?, 1)?;
+        let count = integer(next("number of lattice vectors")?, 1)?;
+        if band >= size {
+            return Err(LoadError::Parse(format!("band {} is absent, there are {} bands", band, size)));
+        }
+        let mut degeneracies = Vec::with_capacity(count);
+        for _ in 0..count {
+            degeneracies.push(next("degeneracy")?);
+        }
+
+        let mut hoppings: Vec<Hopping> = Vec::new();
+        for (block, &degeneracy) in degeneracies.iter().enumerate() {
+            let mut re = vec![0.0; size * size];
+            let mut im = vec![0.0; size * size];
+            let mut first = None;
+            for _ in 0..size * size {
+                let rs = [next("lattice vector")?, next("lattice vector")?, next("lattice vector")?];
+                if *first.get_or_insert(rs) != rs {
+                    return Err(LoadError::Parse(format!("lattice vector changes inside block {}",
+                                                        block + 1)));
+                }
+                let m = integer(next("orbital index")?, 1)?;
+                let n = integer(next("orbital index")?, 1)?;
+                if m > size || n > size {
+                    return Err(LoadError::Parse(format!("invalid orbital indices {} {}", m, n)));
+                }
+                re[(m - 1) * size + n - 1] = next("hopping")? / degeneracy;
+                im[(m - 1) * size + n - 1] = next("hopping")? / degeneracy;
+            }
+            // hoppings to vectors differing only in third component are merged
+            let rs = first.unwrap();
+            let r = lattice.0 * rs[0] + lattice.1 * rs[1];
+            match hoppings.iter().position(|h| h.r == r) {
+                Some(i) => {
+                    for k in 0..size * size {
+                        hoppings[i].re[k] += re[k];
+                        hoppings[i].im[k] += im[k];
+                    }
+                }
+                None => hoppings.push(Hopping { r, re, im }),
+            }
+        }
+
+        let area = lattice.0.area(lattice.1);
+        let b1 = lattice.1.cross() * (2.0 * PI / area);
+        let b2 = lattice.0.cross() * (-2.0 * PI / area);
+        let mut m = Wannier {
+            band,
+            phonons,
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            size,
+            hoppings,
+            bz: BrillouinZone::wigner_seitz(b1, b2),
+            dos: DosTable::empty(),
+            table: None,
+        };
+        let grid: Vec<Vec<f64>> = (0..GRID_POINTS)
+            .map(|i| {
+                (0..GRID_POINTS)
+                    .map(|j| {
+                        m.energy(b1 * (i as f64 / GRID_POINTS as f64) +
+                                 b2 * (j as f64 / GRID_POINTS as f64))
+                    })
+                    .collect()
+            })
+            .collect();
+        m.dos = DosTable::from_grid(b1.area(b2).abs(), &grid, DOS_POINTS);
+        m.table = Tabulated::new(m.bz.clone(), grid, m.phonons.clone()).ok();
+        Ok(m)
+    }
+
+    /// Number of bands of hamiltonian
+    pub fn bands(&self) -> usize {
+        self.size
+    }
+
+    /// Hamiltonian $H(p)$ and its derivatives by $p\_x$, $p\_y$ embedded into real symmetric
+    /// matrices $\begin{pmatrix} Re H & -Im H \\\\ Im H & Re H \end{pmatrix}$
+    fn hamiltonian(&self, p: Vec2) -> [Vec<f64>; 3] {
+        let n = self.size;
+        let mut hs = [vec![0.0; 4 * n * n], vec![0.0; 4 * n * n], vec![0.0; 4 * n * n]];
+        for h in &self.hoppings {
+            let (s, c) = p.dot(h.r).sin_cos();
+            for i in 0..n {
+                for j in 0..n {
+                    // $e\^{i p R} H(R)$ and derivatives $i R e\^{i p R} H(R)$
+                    let (hr, hi) = (h.re[i * n + j], h.im[i * n + j]);
+                    let (re, im) = (hr * c - hi * s, hr * s + hi * c);
+                    let values = [(re, im), (-im * h.r.x, re * h.r.x), (-im * h.r.y, re * h.r.y)];
+                    for (m, &(re, im)) in hs.iter_mut().zip(values.iter()) {
+                        m[i * 2 * n + j] += re;
+                        m[(i + n) * 2 * n + j + n] += re;
+                        m[(i + n) * 2 * n + j] += im;
+                        m[i * 2 * n + j + n] -= im;
+                    }
+                }
+            }
+        }
+        hs
+    }
+
+    /// Energy and velocity of the band
+    fn solve(&self, p: Vec2) -> (f64, Vec2) {
+        if let Some((id, band, q, e, v)) = LAST.with(|last| last.get()) {
+            if id == self.id && band == self.band && q == p {
+                return (e, v);
+            }
+        }
+        let hs = self.hamiltonian(p);
+        let (values, vectors) = eigh(hs[0].clone(), 2 * self.size);
+        let mut order: Vec<usize> = (0..values.len()).collect();
+        order.sort_by(|&a, &b| values[a].partial_cmp(&values[b]).unwrap());
+        // every eigenvalue of embedding is twice degenerate
+        let k = order[2 * self.band];
+        let x = &vectors[k];
+        let v = Vec2::new(quadratic_form(&hs[1], x), quadratic_form(&hs[2], x));
+        LAST.with(|last| last.set(Some((self.id, self.band, p, values[k], v))));
+        (values[k], v)
+    }
+}
+
+/// Checks that number read from file is integer not less than `min`
+fn integer(x: f64, min: usize) -> Result<usize, LoadError> {
+    if x >= min as f64 && x.fract() == 0.0 && x <= u32::MAX as f64 {
+        Ok(x as usize)
+    } else {
+        Err(LoadError::Parse(format!("invalid integer: {}", x)))
+    }
+}
+
+/// Eigenvalues and eigenvectors of real symmetric matrix `a` of size `n` by cyclic Jacobi method
+fn eigh(mut a: Vec<f64>, n: usize) -> (Vec<f64>, Vec<Vec<f64>>) {
+    let mut v = vec![0.0; n * n];
+    for i in 0..n {
+        v[i * n + i] = 1.0;
+    }
+    let norm = a.iter().map(|x| x * x).sum::<f64>().sqrt();
+    for _ in 0..64 {
+        let off: f64 = (0..n).flat_map(|i| (0..n).filter(move |&j| j != i).map(move |j| (i, j)))
+            .map(|(i, j)| a[i * n + j] * a[i * n + j])
+            .sum();
+        if off.sqrt() <= 1e-15 * norm {
+            break;
+        }
+        for p in 0..n {
+            for q in p + 1..n {
+                let apq = a[p * n + q];
+                if apq == 0.0 {
+                    continue;
+                }
+                let theta = (a[q * n + q] - a[p * n + p]) / (2.0 * apq);
+                let t = theta.signum() / (theta.abs() + (theta * theta + 1.0).sqrt());
+                let c = 1.0 / (t * t + 1.0).sqrt();
+                let s = t * c;
+                for k in 0..n {
+                    let (akp, akq) = (a[k * n + p], a[k * n + q]);
+                    a[k * n + p] = c * akp - s * akq;
+                    a[k * n + q] = s * akp + c * akq;
+                }
+                for k in 0..n {
+                    let (apk, aqk) = (a[p * n + k], a[q * n + k]);
+                    a[p * n + k] = c * apk - s * aqk;
+                    a[q * n + k] = s * apk + c * aqk;
+                }
+                for k in 0..n {
+                    let (vkp, vkq) = (v[k * n + p], v[k * n + q]);
+                    v[k * n + p] = c * vkp - s * vkq;
+                    v[k * n + q] = s * vkp + c * vkq;
+                }
+            }
+        }
+    }
+    let values = (0..n).map(|i| a[i * n + i]).collect();
+    let vectors = (0..n).map(|j| (0..n).map(|i| v[i * n + j]).collect()).collect();
+    (values, vectors)
+}
+
+/// $x\^T A x$ for symmetric matrix `a`
+fn quadratic_form(a: &[f64], x: &[f64]) -> f64 {
+    let n = x.len();
+    (0..n).map(|i| x[i] * (0..n).map(|j| a[i * n + j] * x[j]).sum::<f64>()).sum()
+}
+
+impl Material for Wannier {
+    fn energy(&self, p: Vec2) -> f64 {
+        self.solve(p).0
+    }
+    fn energy_gradient(&self, p: Vec2) -> Vec2 {
+        self.solve(p).1
+    }
+    fn min_energy(&self) -> f64 {
+        self.dos.min
+    }
+    fn max_energy(&self) -> f64 {
+        self.dos.min + self.dos.step * DOS_POINTS as f64
+    }
+    /// Roots of interpolated band refined by Newton method along the ray, there are no
+    /// isoenergy lines in flat band
+    fn momentums(&self, energy: f64, theta: f64) -> Vec<Vec2> {
+        let table = match self.table {
+            Some(ref table) => table,
+            None => return Vec::new(),
+        };
+        let n = Vec2::from_polar(1.0, theta);
+        let pmax = self.bz.pmax(theta);
+        let width = self.max_energy() - self.min_energy();
+        let mut ps = Vec::new();
+        for p in table.momentums(energy, theta) {
+            let mut x = p.dot(n);
+            for _ in 0..NEWTON_ITERATIONS {
+                let (e, v) = self.solve(n * x);
+                let dx = (e - energy) / v.dot(n);
+                if !dx.is_finite() {
+                    break;
+                }
+                x = (x - dx).max(0.0).min(pmax);
+                if dx.abs() <= 1e-14 * pmax {
+                    break;
+                }
+            }
+            // roots of interpolation near extremum of band may be absent in exact band
+            if (self.energy(n * x) - energy).abs() <= 1e-10 * width {
+                ps.push(n * x);
+            }
+        }
+        ps
+    }
+    fn brillouin_zone(&self) -> &BrillouinZone {
+        &self.bz
+    }
+    fn optical_energy(&self) -> f64 {
+        self.phonons.optical_energy
+    }
+    fn optical_scattering(&self, p: Vec2) -> f64 {
+        let e = self.energy(p) - self.optical_energy();
+        self.phonons.optical_rate(self.dos.get(e))
+    }
+    fn optical_absorption(&self, p: Vec2) -> f64 {
+        let e = self.energy(p) + self.optical_energy();
+        self.phonons.absorption_rate(self.dos.get(e))
+    }
+    fn acoustic_scattering(&self, p: Vec2) -> f64 {
+        self.phonons.acoustic_rate(self.dos.get(self.energy(p)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use material::ray_roots;
+    use validation::check;
+
+    /// Square lattice with on-site energy 1 and nearest neighbour hopping $-t = -0.25$
+    const SQUARE: &str = "written by hand
+ 1
+ 5
+ 1 1 1 1 1
+ 0 0 0 1 1 1.0 0.0
+ 1 0 0 1 1 -0.25 0.0
+-1 0 0 1 1 -0.25 0.0
+ 0 1 0 1 1 -0.25 0.0
+ 0 -1 0 1 1 -0.25 0.0
+";
+
+    /// Honeycomb lattice with nearest neighbour hopping $-t = -1$, $R\_3 = \pm 1$ halves
+    /// of intra-cell hopping check summation over third lattice vector
+    const HONEYCOMB: &str = "written by hand
+ 2
+ 6
+ 1 1 1 1 1 1
+ 0 0 1 1 1 0.0 0.0
+ 0 0 1 1 2 -0.5 0.0
+ 0 0 1 2 1 -0.5 0.0
+ 0 0 1 2 2 0.0 0.0
+ 0 0 -1 1 1 0.0 0.0
+ 0 0 -1 1 2 -0.5 0.0
+ 0 0 -1 2 1 -0.5 0.0
+ 0 0 -1 2 2 0.0 0.0
+-1 0 0 1 1 0.0 0.0
+-1 0 0 1 2 -1.0 0.0
+-1 0 0 2 1 0.0 0.0
+-1 0 0 2 2 0.0 0.0
+ 1 0 0 1 1 0.0 0.0
+ 1 0 0 1 2 0.0 0.0
+ 1 0 0 2 1 -1.0 0.0
+ 1 0 0 2 2 0.0 0.0
+ 0 -1 0 1 1 0.0 0.0
+ 0 -1 0 1 2 -1.0 0.0
+ 0 -1 0 2 1 0.0 0.0
+ 0 -1 0 2 2 0.0 0.0
+ 0 1 0 1 1 0.0 0.0
+ 0 1 0 1 2 0.0 0.0
+ 0 1 0 2 1 -1.0 0.0
+ 0 1 0 2 2 0.0 0.0
+";
+
+    fn honeycomb_lattice() -> (Vec2, Vec2) {
+        (Vec2::new(1.0, 0.0), Vec2::new(0.5, 3f64.sqrt() / 2.0))
+    }
+
+    #[test]
+    fn test_eigh() {
+        let (values, vectors) = eigh(vec![2.0, 1.0, 0.0, 1.0, 2.0, 0.5, 0.0, 0.5, -1.0], 3);
+        let a = [2.0, 1.0, 0.0, 1.0, 2.0, 0.5, 0.0, 0.5, -1.0];
+        for (&l, x) in values.iter().zip(vectors.iter()) {
+            for i in 0..3 {
+                let ax: f64 = (0..3).map(|j| a[i * 3 + j] * x[j]).sum();
+                assert!((ax - l * x[i]).abs() < 1e-12);
+            }
+        }
+    }
+
+    #[test]
+    fn test_square_lattice() {
+        let lattice = (Vec2::new(1.0, 0.0), Vec2::new(0.0, 1.0));
+        let m = Wannier::parse(SQUARE, lattice, 0, Phonons::new(0.2, 0.1, 0.1)).unwrap();
+        for &p in &[Vec2::new(0.3, -0.7), Vec2::new(-2.9, 1.4), Vec2::zero()] {
+            let e = 1.0 - 0.5 * (p.x.cos() + p.y.cos());
+            assert!((m.energy(p) - e).abs() < 1e-12);
+            let v = Vec2::new(0.5 * p.x.sin(), 0.5 * p.y.sin());
+            assert!((m.velocity(p) - v).len() < 1e-12);
+        }
+        assert!((m.min_energy() - 0.0).abs() < 1e-12);
+        assert!((m.max_energy() - 2.0).abs() < 1e-12);
+        assert!((m.brillouin_zone().basis().0 - Vec2::new(2.0 * PI, 0.0)).len() < 1e-12);
+        assert!(check(&m, 10, 1e-4).is_ok());
+    }
+
+    #[test]
+    fn test_honeycomb() {
+        let lattice = honeycomb_lattice();
+        let (a1, a2) = lattice;
+        let lower = Wannier::parse(HONEYCOMB, lattice, 0, Phonons::new(0.2, 0.1, 0.1)).unwrap();
+        let upper = Wannier::parse(HONEYCOMB, lattice, 1, Phonons::new(0.2, 0.1, 0.1)).unwrap();
+        assert_eq!(upper.bands(), 2);
+        let h = 1e-6;
+        for &p in &[Vec2::new(0.3, -0.7), Vec2::new(-2.9, 1.4), Vec2::new(1.7, 0.05)] {
+            // $|1 + e\^{-i p a\_1} + e\^{-i p a\_2}|$
+            let f = Vec2::new(1.0 + p.dot(a1).cos() + p.dot(a2).cos(),
+                              p.dot(a1).sin() + p.dot(a2).sin())
+                .len();
+            assert!((lower.energy(p) + f).abs() < 1e-12);
+            assert!((upper.energy(p) - f).abs() < 1e-12);
+            for m in &[&lower, &upper] {
+                let fd = Vec2::new(m.energy(p + Vec2::new(h, 0.0)) - m.energy(p - Vec2::new(h, 0.0)),
+                                   m.energy(p + Vec2::new(0.0, h)) - m.energy(p - Vec2::new(0.0, h))) /
+                         (2.0 * h);
+                assert!((m.velocity(p) - fd).len() < 1e-8);
+            }
+        }
+        // Dirac point at corner of hexagonal zone
+        let k = upper.brillouin_zone().vertices()[0];
+        assert!(upper.energy(k).abs() < 1e-12);
+        assert!((upper.max_energy() - 3.0).abs() < 1e-12);
+        assert!((lower.min_energy() + 3.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_momentums() {
+        let phonons = Phonons::new(0.2, 0.1, 0.1);
+        let m = Wannier::parse(HONEYCOMB, honeycomb_lattice(), 1, phonons).unwrap();
+        for &e in &[0.3, 1.0, 1.5, 2.9] {
+            for i in 0..16 {
+                let theta = 0.4 * i as f64;
+                let n = Vec2::from_polar(1.0, theta);
+                // default search of roots in exact band
+                let exact = ray_roots(|p| m.energy(n * p) - e,
+                                      |p| m.velocity(n * p).dot(n),
+                                      m.brillouin_zone().pmax(theta),
+                                      64);
+                let ps = m.momentums(e, theta);
+                assert_eq!(ps.len(), exact.len());
+                for (p, x) in ps.iter().zip(exact.iter()) {
+                    assert!((p.len() - x).abs() < 1e-10);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_flat_band() {
+        let lattice = (Vec2::new(1.0, 0.0), Vec2::new(0.0, 1.0));
+        let flat = SQUARE.replace("-0.25", "0.0");
+        let m = Wannier::parse(&flat, lattice, 0, Phonons::new(0.2, 0.1, 0.1)).unwrap();
+        let p = Vec2::new(0.3, -0.7);
+        assert_eq!(m.min_energy(), m.max_energy());
+        assert_eq!(m.acoustic_scattering(p), 0.0);
+        assert_eq!(m.optical_scattering(p), 0.0);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let lattice = (Vec2::new(1.0, 0.0), Vec2::new(0.0, 1.0));
+        let phonons = Phonons::new(0.2, 0.1, 0.1);
+        assert!(Wannier::parse(SQUARE, lattice, 1, phonons.clone()).is_err());
+        assert!(Wannier::parse(&SQUARE[..60], lattice, 0, phonons.clone()).is_err());
+        assert!(Wannier::parse(&SQUARE.replace("-0.25", "x"), lattice, 0, phonons.clone()).is_err());
+        assert!(Wannier::load("/nonexistent", lattice, 0, phonons.clone()).is_err());
+        for &(from, to) in &[("\n 1\n 5", "\n -1\n 5"), ("\n 1\n 5", "\n 1.5\n 5"),
+                             ("0 0 0 1 1", "0 0 0 0 1"), ("1 0 0 1 1", "1 0 0 1 1.5")] {
+            assert!(Wannier::parse(&SQUARE.replace(from, to), lattice, 0, phonons.clone()).is_err());
+        }
+    }
+
+    #[test]
+    fn test_block_lattice_vector() {
+        let phonons = Phonons::new(0.2, 0.1, 0.1);
+        // the last line of the first block belongs to other lattice vector
+        let text = HONEYCOMB.replace(" 0 0 1 2 2 0.0 0.0", " 0 0 -1 2 2 0.0 0.0");
+        match Wannier::parse(&text, honeycomb_lattice(), 0, phonons.clone()) {
+            Err(LoadError::Parse(s)) => assert!(s.contains("block 1")),
+            _ => panic!("inconsistent block is accepted"),
+        }
+        assert!(Wannier::parse(HONEYCOMB, honeycomb_lattice(), 0, phonons).is_ok());
+    }
+
+    #[test]
+    fn test_cache() {
+        let lattice = honeycomb_lattice();
+        let mut m = Wannier::parse(HONEYCOMB, lattice, 0, Phonons::new(0.2, 0.1, 0.1)).unwrap();
+        let p = Vec2::new(0.3, -0.7);
+        let (e, v) = (m.energy(p), m.velocity(p));
+        m.band = 1;
+        assert!((m.energy(p) + e).abs() < 1e-12);
+        assert!((m.velocity(p) + v).len() < 1e-12);
+        m.band = 0;
+        assert_eq!(m.energy(p), e);
+        // copy in other thread gets the same values
+        let copy = m.clone();
+        let (e2, v2) = ::std::thread::spawn(move || (copy.energy(p), copy.velocity(p))).join().unwrap();
+        assert_eq!((e, v.x, v.y), (e2, v2.x, v2.y));
+    }
+}
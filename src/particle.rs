@@ -1,73 +1,339 @@
 //! Particle one particle movement in material under electromagnetic fields with phonon scattering
 
-use material::Material;
-use fields::Fields;
+use material::MultiBand;
+use fields::FieldSource;
 use linal::Vec2;
-use rng::Rng;
+use rng::{RandomGenerator, Rng};
+use integrators::{Integrator, RungeKutta};
+use scattering::{checked_rate, max_rate, PhononProcess};
 use stats::Histogram;
 
-fn runge<F>(p: Vec2, force: F, t: f64, dt: f64) -> Vec2
-    where F: Fn(Vec2, f64) -> Vec2
-{
+/// Ratio of constant probability of free flight to estimated maximal scattering probability
+const GAMMA_MARGIN: f64 = 1.2;
 
-    let k1 = force(p, t);
-    let k2 = force(p + k1 * dt / 2.0, t + dt / 2.0);
-    let k3 = force(p + k2 * dt / 2.0, t + dt / 2.0);
-    let k4 = force(p + k3 * dt, t + dt);
+/// Minimal expected number of events of free flight algorithm per run, so that scattering
+/// is noticed even when estimated maximal probability is zero
+const MIN_EVENTS: f64 = 16.0;
 
-    p + (k1 + k2 * 2.0 + k3 * 2.0 + k4) * dt / 6.0
+/// Default number of points of trajectory in `Summary`, see `Sampling::trajectory`
+pub const TRAJECTORY_POINTS: usize = 64;
+
+#[test]
+fn self_scattering_event_times() {
+    use material::BrillouinZone;
+    use materials::{Parabolic, Phonons};
+    use {Fields, RunOptions, Stats, create_ensemble_seeded, run_ensemble};
+
+    // scattering times are not quantized by time step: acoustic probability is 0.5,
+    // so there are 10 events per particle even with single step between them
+    let bz = BrillouinZone::new(Vec2::new(-1.0, -1.0),
+                                Vec2::new(1.0, -1.0),
+                                Vec2::new(-1.0, 1.0));
+    let m = &Parabolic::new(10.0, bz, Phonons::new(1.0, 0.05, 0.1));
+    let f = &Fields::zero();
+    let stats = Stats::from_ensemble(&run_ensemble(&create_ensemble_seeded(50, m, 7e-3, 3),
+                                                   10.0,
+                                                   20.0,
+                                                   f,
+                                                   &RunOptions::default()));
+    assert!((stats.acoustic - 10.0).abs() < 1.0, "{}", stats.acoustic);
+    assert_eq!(stats.optical, 0.0);
+    // maximal probability includes optical emission above phonon energy
+    assert!(stats.self_scattering > 0.0);
 }
 
 #[test]
-fn runge_circle() {
-    use std::f64::consts::PI;
-    let f = |p: Vec2, _: f64| p.cross();
-    let dt = 0.01;
-    let mut p = Vec2::new(1.0, 0.0);
-    let mut t = 0.0;
-    while t < PI {
-        p = runge(p, &f, t, dt);
-        t += dt;
+fn underestimated_free_flight_rate() {
+    use material::BrillouinZone;
+    use materials::{Parabolic, Phonons};
+    use {Fields, Stats, create_ensemble_seeded};
+
+    // acoustic probability is 0.5, flights drawn with too small or zero rate are drawn again,
+    // which doesn't bias constant probability
+    let bz = BrillouinZone::new(Vec2::new(-1.0, -1.0),
+                                Vec2::new(1.0, -1.0),
+                                Vec2::new(-1.0, 1.0));
+    let m = &Parabolic::new(10.0, bz, Phonons::new(1.0, 0.05, 0.1));
+    let f = &Fields::zero();
+    for &gamma in &[0.0, 0.05] {
+        let options = &RunOptions { free_flight_rate: Some(gamma), ..RunOptions::default() };
+        let summaries: Vec<Summary> = create_ensemble_seeded(200, m, 7e-3, 3)
+            .iter()
+            .map(|x| x.run_with(1e-1, 20.0, f, options))
+            .collect();
+        let stats = Stats::from_ensemble(&summaries);
+        assert!((stats.acoustic - 10.0).abs() < 0.7, "{} {}", gamma, stats.acoustic);
     }
-    p = runge(p, &f, t, PI - t);
-    assert!((p - Vec2::new(-1.0, 0.0)).len() < 1e-8);
 }
 
 #[test]
-fn runge_parabola() {
-    let f = |_: Vec2, t: f64| Vec2::new(0.0, t);
-    let dt = 0.01;
-    let mut p = Vec2::new(1.0, 0.0);
-    let mut t = 0.0;
-    while t < 1.0 {
-        p = runge(p, &f, t, dt);
-        t += dt;
+#[should_panic(expected = "mechanism undefined gives invalid probability NaN")]
+fn undefined_rate() {
+    use material::BrillouinZone;
+    use materials::{Parabolic, Phonons};
+    use scattering::ScatteringMechanism;
+    use test_support::with_mechanisms;
+    use Fields;
+
+    struct Undefined;
+
+    impl ScatteringMechanism for Undefined {
+        fn name(&self) -> &str {
+            "undefined"
+        }
+        fn rate(&self, _m: &dyn MultiBand, _from: usize, _to: usize, _p: Vec2) -> f64 {
+            f64::NAN
+        }
+        fn energy_change(&self, _m: &dyn MultiBand, _from: usize, _to: usize) -> f64 {
+            0.0
+        }
+    }
+
+    // given probability of free flight skips estimate of maximal probability
+    let bz = BrillouinZone::new(Vec2::new(-1.0, -1.0),
+                                Vec2::new(1.0, -1.0),
+                                Vec2::new(-1.0, 1.0));
+    let m = &with_mechanisms(Parabolic::new(10.0, bz, Phonons::new(1.0, 0.05, 0.1)),
+                             || vec![Box::new(Undefined)]);
+    let options = &RunOptions { free_flight_rate: Some(1.0), ..RunOptions::default() };
+    Particle::new(m, Vec2::new(0.01, 0.0), 1).run_with(1e-1, 20.0, &Fields::zero(), options);
+}
+
+#[test]
+#[should_panic(expected = "invalid probability of free flight NaN")]
+fn undefined_free_flight_rate() {
+    use material::BrillouinZone;
+    use materials::{Parabolic, Phonons};
+    use Fields;
+
+    let bz = BrillouinZone::new(Vec2::new(-1.0, -1.0),
+                                Vec2::new(1.0, -1.0),
+                                Vec2::new(-1.0, 1.0));
+    let m = &Parabolic::new(10.0, bz, Phonons::new(1.0, 0.05, 0.1));
+    let options = &RunOptions { free_flight_rate: Some(f64::NAN), ..RunOptions::default() };
+    Particle::new(m, Vec2::new(0.01, 0.0), 1).run_with(1e-1, 20.0, &Fields::zero(), options);
+}
+
+#[test]
+fn underestimated_upper_bound() {
+    use material::BrillouinZone;
+    use materials::{Parabolic, Phonons};
+    use {Fields, Stats, create_ensemble_seeded, run_ensemble};
+
+    // probability jumps at threshold of optical emission, which field reaches
+    let bz = BrillouinZone::new(Vec2::new(-1.0, -1.0),
+                                Vec2::new(1.0, -1.0),
+                                Vec2::new(-1.0, 1.0));
+    let m = &Parabolic::new(10.0, bz, Phonons::new(5e-3, 0.05, 0.5));
+    let f = &Fields::new((Vec2::new(0.05, 0.0), Vec2::zero(), Vec2::zero()),
+                         (0.0, 0.0, 0.0),
+                         (0.0, 0.0),
+                         0.0);
+    let ensemble = create_ensemble_seeded(400, m, 1e-3, 3);
+    let stats = |rate: Option<f64>| {
+        let options = &RunOptions { free_flight_rate: rate, ..RunOptions::default() };
+        Stats::from_ensemble(&run_ensemble(&ensemble, 1e-1, 20.0, f, options))
+    };
+    // estimate is upper bound, while flights drawn with zero rate (that is rate for
+    // `MIN_EVENTS`) are redrawn above threshold, which makes emission rarer
+    let (estimated, underestimated) = (stats(None), stats(Some(0.0)));
+    assert_eq!(estimated.redrawn, 0.0);
+    assert!(underestimated.redrawn > 0.5);
+    assert!(underestimated.optical < estimated.optical - 0.1,
+            "{} {}",
+            underestimated.optical,
+            estimated.optical);
+}
+
+#[test]
+fn trajectory_points() {
+    use material::BrillouinZone;
+    use materials::{Parabolic, Phonons};
+    use {Fields, create_ensemble_seeded};
+
+    let bz = BrillouinZone::new(Vec2::new(-1.0, -1.0),
+                                Vec2::new(1.0, -1.0),
+                                Vec2::new(-1.0, 1.0));
+    let m = &Parabolic::new(10.0, bz, Phonons::new(1.0, 0.05, 0.1));
+    let f = &Fields::new((Vec2::new(0.01, 0.0), Vec2::new(0.0, 0.02), Vec2::zero()),
+                         (0.0, 0.0, 0.0),
+                         (1.0, 0.0),
+                         0.0);
+    let particle = &create_ensemble_seeded(1, m, 7e-3, 3)[0];
+    for &points in &[0, 5] {
+        let sampling = Sampling { trajectory: points, ..Sampling::none() };
+        let s = particle.run_with(1e-1, 20.0, f, &RunOptions { sampling, ..RunOptions::default() });
+        assert_eq!(s.trajectory.len(), points);
+        // average velocity and displacement are the same integral
+        let error = (s.average_speed * 20.0 - s.displacement).len();
+        assert!(error < 1e-12, "{}", error);
+        if points > 0 {
+            let (t, r) = s.trajectory[points - 1];
+            assert_eq!(t, 20.0);
+            assert!((r - s.displacement).len() < 1e-12);
+        }
     }
-    p = runge(p, &f, t, 1.0 - t);
-    assert!((p - Vec2::new(1.0, 0.5)).len() < 1e-8);
 }
 
 #[test]
-fn runge_sin() {
-    use std::f64::consts::PI;
-    let f = |_: Vec2, t: f64| Vec2::new(0.0, t.sin());
-    let dt = 0.01;
-    let mut p = Vec2::new(1.0, 0.0);
-    let mut t = 0.0;
-    while t < PI {
-        p = runge(p, &f, t, dt);
-        t += dt;
+fn trapezoidal_energy() {
+    use material::BrillouinZone;
+    use materials::{Parabolic, Phonons};
+    use test_support::with_mechanisms;
+    use Fields;
+
+    // without scattering energy $p\^2 / 2m$ grows as $t\^2$ and its mean is $p\^2(T) / 6m$,
+    // steps end at self-scattering events, so they are about 0.1, and trapezoids are accurate
+    // to 1% while rectangles give error of 20%
+    let bz = BrillouinZone::new(Vec2::new(-1.0, -1.0),
+                                Vec2::new(1.0, -1.0),
+                                Vec2::new(-1.0, 1.0));
+    let m = &with_mechanisms(Parabolic::new(10.0, bz, Phonons::new(1.0, 0.05, 0.1)), Vec::new);
+    let f = &Fields::new((Vec2::new(0.01, 0.0), Vec2::zero(), Vec2::zero()),
+                         (0.0, 0.0, 0.0),
+                         (0.0, 0.0),
+                         0.0);
+    let options = &RunOptions { free_flight_rate: Some(0.0), ..RunOptions::default() };
+    let s = Particle::new(m, Vec2::zero(), 1).run_with(1.0, 2.0, f, options);
+    let expected = 4e-4 / 60.0;
+    assert!((s.energy / expected - 1.0).abs() < 0.02, "{}", s.energy);
+}
+
+#[test]
+#[should_panic(expected = "sample times are not increasing")]
+fn unsorted_sample_times() {
+    use material::BrillouinZone;
+    use materials::{Parabolic, Phonons};
+    use {Fields, create_ensemble_seeded};
+
+    let bz = BrillouinZone::new(Vec2::new(-1.0, -1.0),
+                                Vec2::new(1.0, -1.0),
+                                Vec2::new(-1.0, 1.0));
+    let m = &Parabolic::new(10.0, bz, Phonons::new(1.0, 0.05, 0.1));
+    let sampling = Sampling::new(vec![2.0, 1.0], vec![]);
+    create_ensemble_seeded(1, m, 7e-3, 3)[0]
+        .run_with(1e-1, 5.0, &Fields::zero(), &RunOptions { sampling, ..RunOptions::default() });
+}
+
+#[derive(Clone)]
+/// Events of one scattering mechanism
+pub struct MechanismSummary {
+    pub name: String,
+    /// number of events
+    pub count: u32,
+    /// number of events, for which no final state was found, they don't change particle state
+    /// and are not included in `count`
+    pub failed: u32,
+    /// histogram of directions of momentum before scattering
+    pub from_theta: Vec<usize>,
+    /// histogram of directions of momentum after scattering
+    pub to_theta: Vec<usize>,
+    /// histogram of phases of the first harmonic of field at scattering
+    pub field_phase: Vec<usize>,
+}
+
+#[derive(Clone)]
+/// Quantities recorded during run besides time averages
+pub struct Sampling {
+    /// increasing times, at which velocity and energy are recorded
+    pub times: Vec<f64>,
+    /// frequencies of Fourier components of velocity, see `Summary::harmonics`
+    pub frequencies: Vec<f64>,
+    /// number of evenly spaced points of `Summary::trajectory`, zero turns it off
+    pub trajectory: usize,
+}
+
+impl Sampling {
+    pub fn new(times: Vec<f64>, frequencies: Vec<f64>) -> Sampling {
+        Sampling { times, frequencies, trajectory: TRAJECTORY_POINTS }
+    }
+    /// Records only trajectory
+    pub fn none() -> Sampling {
+        Sampling::new(vec![], vec![])
     }
-    p = runge(p, &f, t, PI - t);
-    assert!((p - Vec2::new(1.0, 2.0)).len() < 1e-8);
 }
 
+#[derive(Clone)]
+/// Options of `Particle::run_with` and `run_ensemble`, default ones integrate equations of
+/// motion by Runge-Kutta method, record only trajectory and use all available cores
+pub struct RunOptions<'a> {
+    pub integrator: &'a dyn Integrator,
+    pub sampling: Sampling,
+    /// number of threads of `run_ensemble`, 0 means number of available cores
+    pub threads: usize,
+    /// initial probability of free flight, estimated by `free_flight_rate` if not given
+    pub free_flight_rate: Option<f64>,
+}
+
+impl Default for RunOptions<'static> {
+    fn default() -> RunOptions<'static> {
+        RunOptions {
+            integrator: &RungeKutta,
+            sampling: Sampling::none(),
+            threads: 0,
+            free_flight_rate: None,
+        }
+    }
+}
+
+#[derive(Clone)]
+/// Instantaneous state of particle
+pub struct Sample {
+    pub t: f64,
+    pub velocity: Vec2,
+    pub energy: f64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+/// Complex amplitude $\mathcal{V} = V\' + i V\'\'$ of harmonic $\Re \mathcal{V} e\^{i \omega t}$
+/// of velocity
+pub struct Amplitude {
+    pub re: Vec2,
+    pub im: Vec2,
+}
+
+impl Amplitude {
+    pub fn new(re: Vec2, im: Vec2) -> Amplitude {
+        Amplitude { re, im }
+    }
+}
 
 #[derive(Clone)]
 pub struct Summary {
     pub average_speed: Vec2,
     pub acoustic: u32,
+    /// number of optical phonon emissions
     pub optical: u32,
+    /// number of optical phonon absorptions
+    pub optical_absorption: u32,
+    /// number of scattering events, which moved particle to other band of the same valley
+    pub interband: u32,
+    /// number of scattering events, which moved particle to other valley
+    pub intervalley: u32,
+    /// fraction of time spent in every valley
+    pub valley_occupation: Vec<f64>,
+    /// contribution of every valley to average velocity
+    pub valley_current: Vec<Vec2>,
+    /// events of every mechanism of material
+    pub mechanisms: Vec<MechanismSummary>,
+    /// displacement in real space at the end of run
+    pub displacement: Vec2,
+    /// displacements at `Sampling::trajectory` evenly spaced times, last of them is the end
+    /// of run; growth of mean square displacement of ensemble gives diffusion coefficient
+    pub trajectory: Vec<(f64, Vec2)>,
+    /// velocity and energy at times requested in `Sampling`
+    pub samples: Vec<Sample>,
+    /// frequencies requested in `Sampling` and complex amplitudes of velocity
+    /// $v(t) = \Re \sum\_\omega \mathcal{V}\_\omega e\^{i \omega t}$ at them, found as
+    /// $\mathcal{V}\_\omega = 2 \int v(t) e\^{-i \omega t} dt / t$ ($\mathcal{V}\_0$ is average velocity)
+    pub harmonics: Vec<(f64, Amplitude)>,
+    /// number of scattering attempts, for which no final state was found
+    pub failed: u32,
+    /// number of fictitious self-scattering events of free flight algorithm
+    pub self_scattering: u32,
+    /// number of flights drawn again, as probability of free flight was not upper bound
+    /// of total scattering probability, see `Particle::run_with`
+    pub redrawn: u32,
     pub tau: f64,
     pub energy: f64,
     pub from_theta_ac: Vec<usize>,
@@ -86,12 +352,7 @@ impl Summary {
             optical: o,
             tau: t,
             energy: e,
-            from_theta_ac: vec![],
-            to_theta_ac: vec![],
-            from_theta_op: vec![],
-            to_theta_op: vec![],
-            field_phase_ac: vec![],
-            field_phase_op: vec![],
+            ..Summary::empty()
         }
     }
     pub fn empty() -> Summary {
@@ -99,6 +360,19 @@ impl Summary {
             average_speed: Vec2::zero(),
             acoustic: 0,
             optical: 0,
+            optical_absorption: 0,
+            interband: 0,
+            intervalley: 0,
+            valley_occupation: vec![],
+            valley_current: vec![],
+            mechanisms: vec![],
+            displacement: Vec2::zero(),
+            trajectory: vec![],
+            samples: vec![],
+            harmonics: vec![],
+            failed: 0,
+            self_scattering: 0,
+            redrawn: 0,
             tau: 0.0,
             energy: 0.0,
             from_theta_ac: vec![],
@@ -109,137 +383,341 @@ impl Summary {
             field_phase_op: vec![],
         }
     }
+
+    /// Events of mechanism with given name
+    pub fn mechanism(&self, name: &str) -> Option<&MechanismSummary> {
+        self.mechanisms.iter().find(|x| x.name == name)
+    }
 }
 
-#[derive(Debug)]
-enum Scattering {
-    Acoustic,
-    Optical,
+/// Constant probability of free flight for material `m`: estimate of maximal total
+/// probability of scattering by `MultiBand::band_mechanisms` with margin
+pub fn free_flight_rate(m: &dyn MultiBand) -> f64 {
+    max_rate(m, &m.band_mechanisms()) * GAMMA_MARGIN
 }
 
-pub struct Particle<'a, T: 'a + Material> {
+pub struct Particle<'a, T: 'a + MultiBand, R: RandomGenerator = Rng> {
     pub init_condition: Vec2,
-    seed: u32,
+    /// initial band
+    pub band: usize,
+    rng: R,
     m: &'a T,
 }
 
-impl<'a, T: 'a + Material> Particle<'a, T> {
-    pub fn new(m: &T, init_condition: Vec2, seed: u32) -> Particle<T> {
+impl<'a, T: 'a + MultiBand> Particle<'a, T> {
+    pub fn new(m: &T, init_condition: Vec2, seed: u32) -> Particle<'_, T> {
+        Particle::with_rng(m, init_condition, Rng::new(seed))
+    }
+}
+
+impl<'a, T: 'a + MultiBand, R: RandomGenerator> Particle<'a, T, R> {
+    /// Creates particle, which draws scattering events from given random generator
+    pub fn with_rng(m: &T, init_condition: Vec2, rng: R) -> Particle<'_, T, R> {
+        Particle::with_band(m, 0, init_condition, rng)
+    }
+
+    /// Creates particle starting in given band
+    pub fn with_band(m: &T, band: usize, init_condition: Vec2, rng: R) -> Particle<'_, T, R> {
         Particle {
-            m: m,
-            init_condition: init_condition,
-            seed: seed,
+            m,
+            init_condition,
+            band,
+            rng,
         }
     }
 
-    pub fn run(&self, dt: f64, all_time: f64, f: &Fields) -> Summary {
+    /// Material, in which particle moves
+    pub fn material(&self) -> &'a T {
+        self.m
+    }
+
+    /// Runs particle for time `all_time` with step `dt`, integrating equations of motion
+    /// by Runge-Kutta method
+    pub fn run(&self, dt: f64, all_time: f64, f: &dyn FieldSource) -> Summary {
+        self.run_with(dt, all_time, f, &RunOptions::default())
+    }
+
+    /// Runs particle for time `all_time` with step `dt`, integrating equations of motion
+    /// by `options.integrator` and recording quantities requested in `options.sampling`.
+    /// Sample times after `all_time` are skipped, step `dt` should be small compared
+    /// with periods of sampled frequencies.
+    ///
+    /// Probability of free flight should be upper bound of total scattering probability,
+    /// `free_flight_rate` estimates it with margin. Flight, at the end of which total
+    /// probability is greater, is drawn again from the previous event with increased
+    /// probability of free flight. This keeps events real, but flights into states with
+    /// underestimated probability become rarer, so results are biased while `redrawn`
+    /// of summary isn't zero.
+    ///
+    /// Panics if sample times are not increasing, probability of free flight is negative
+    /// or not finite or some mechanism gives not finite probability.
+    pub fn run_with(&self,
+                    dt: f64,
+                    all_time: f64,
+                    f: &dyn FieldSource,
+                    options: &RunOptions)
+                    -> Summary {
         use std::f64::consts::PI;
 
-        let mut rng = Rng::new(self.seed);
+        let integrator = options.integrator;
+        let sampling = &options.sampling;
+        assert!(sampling.times.windows(2).all(|x| x[0] < x[1]),
+                "sample times are not increasing");
+        let gamma = options.free_flight_rate.unwrap_or_else(|| free_flight_rate(self.m));
+        assert!(gamma >= 0.0 && gamma.is_finite(),
+                "invalid probability of free flight {}",
+                gamma);
+        let m: &dyn MultiBand = self.m;
+        let mut rng = self.rng.clone();
         let mut p = self.init_condition;
+        let mut band = self.band;
 
         let mut t = 0.0;
-        let mut wsum: f64 = 0.0;
 
-        let mut n_ac = 0;
-        let mut n_opt = 0;
-        let mut int_v_dt = Vec2::zero();
+        let mut n_inter = 0;
+        let mut n_iv = 0;
+        let mut n_self = 0;
+        let mut n_redrawn = 0;
         let mut int_e_dt: f64 = 0.0;
+        // смещение в реальном пространстве, оно же интеграл скорости по времени
+        let mut r = Vec2::zero();
+        let points = sampling.trajectory;
+        let mut trajectory = Vec::with_capacity(points);
+        let sample_time = |k: usize| all_time * (k + 1) as f64 / points as f64;
+        let samples = &sampling.times;
+        let mut sampled = Vec::with_capacity(samples.len());
+        // интегралы скорости с cos и sin для синхронного детектирования
+        let mut int_v_cos = vec![Vec2::zero(); sampling.frequencies.len()];
+        let mut int_v_sin = vec![Vec2::zero(); sampling.frequencies.len()];
+        while sampled.len() < samples.len() && samples[sampled.len()] <= 0.0 {
+            sampled.push(Sample {
+                t: samples[sampled.len()],
+                velocity: self.m.band_velocity(band, p),
+                energy: self.m.band_energy(band, p),
+            });
+        }
 
+        let mechanisms = self.m.band_mechanisms();
         let n_bins = 256;
-        let mut from_theta_ac = Histogram::new(0.0, 2.0 * PI, n_bins);
-        let mut to_theta_ac = Histogram::new(0.0, 2.0 * PI, n_bins);
-        let mut from_theta_op = Histogram::new(0.0, 2.0 * PI, n_bins);
-        let mut to_theta_op = Histogram::new(0.0, 2.0 * PI, n_bins);
-        let mut field_phase_ac = Histogram::new(0.0, 2.0 * PI, n_bins);
-        let mut field_phase_op = Histogram::new(0.0, 2.0 * PI, n_bins);
-
-        let force = |p: Vec2, t: f64| -> Vec2 {
-            -(f.e.0 + f.e.1 * (f.omega.1 * t).cos() + f.e.2 * (f.omega.2 * t + f.phi).cos() +
-              self.m.velocity(p).cross() *
-              (f.b.0 + f.b.1 * (f.omega.1 * t).cos() + f.b.2 * (f.omega.2 * t + f.phi).cos()))
-        };
+        let mut counts = vec![0; mechanisms.len()];
+        let mut failed = vec![0; mechanisms.len()];
+        let mut from_theta: Vec<Histogram> =
+            mechanisms.iter().map(|_| Histogram::new(0.0, 2.0 * PI, n_bins)).collect();
+        let mut to_theta: Vec<Histogram> =
+            mechanisms.iter().map(|_| Histogram::new(0.0, 2.0 * PI, n_bins)).collect();
+        let mut field_phase: Vec<Histogram> =
+            mechanisms.iter().map(|_| Histogram::new(0.0, 2.0 * PI, n_bins)).collect();
+
+        let bands = self.m.bands();
+        // вероятность рассеяния механизмом i в зону j хранится в rates[i * bands + j]
+        let mut rates = vec![0.0; mechanisms.len() * bands];
+        let mut valley_time = vec![0.0; self.m.valleys()];
+        let mut valley_v_dt = vec![Vec2::zero(); self.m.valleys()];
+        // оценка сверху полной вероятности рассеяния, нулевая оценка заменяется
+        // несколькими событиями за время счета
+        let mut gamma = gamma.max(MIN_EVENTS / all_time);
+        // длины шагов и импульсы в их концах для текущего свободного пробега
+        let mut steps: Vec<(f64, Vec2)> = Vec::new();
 
-        let mut r = -rng.uniform().ln();
         while t < all_time {
-            let v = self.m.velocity(p);
+            // время свободного пробега с постоянной вероятностью рассеяния gamma;
+            // если в его конце полная вероятность рассеяния больше gamma, то пробег
+            // разыгрывается заново из того же состояния с увеличенной gamma
+            let (last, total) = loop {
+                let flight = -rng.uniform().ln() / gamma;
+                let last = flight >= all_time - t;
+                let mut remaining = if last { all_time - t } else { flight };
+                let (mut time, mut q) = (t, p);
+                steps.clear();
+                while remaining > 0.0 {
+                    let h = dt.min(remaining);
+                    // решаем уравнения движения и приводим импульс к зоне
+                    q = integrator.step(q, time, h, f, &|p| self.m.band_velocity(band, p));
+                    q = self.m.zone().to_first_bz(q);
+                    steps.push((h, q));
+                    time += h;
+                    remaining -= h;
+                }
+                if last {
+                    break (true, 0.0);
+                }
+                // вероятности рассеяния всеми механизмами в каждую зону
+                // (0, если выпал из минизоны)
+                let mut total = 0.0;
+                for (i, mechanism) in mechanisms.iter().enumerate() {
+                    for to in 0..bands {
+                        let rate = checked_rate(&**mechanism, m, band, to, q);
+                        rates[i * bands + to] = rate;
+                        total += rate;
+                    }
+                }
+                if total <= gamma {
+                    break (false, total);
+                }
+                gamma = total * GAMMA_MARGIN;
+                n_redrawn += 1;
+            };
 
-            int_v_dt = int_v_dt + v * dt;
+            for &(h, p_end) in &steps {
+                let (v, e_start) = (self.m.band_velocity(band, p), self.m.band_energy(band, p));
+                p = p_end;
+                let (v_end, e_end) = (self.m.band_velocity(band, p), self.m.band_energy(band, p));
 
-            p = runge(p, &force, t, dt); // решаем уравнения движения
+                // скорость и энергию интегрируем по формуле трапеций, в моменты выборки
+                // положение интерполируем линейно
+                let v_dt = (v + v_end) * (h / 2.0);
+                let valley = self.m.valley(band);
+                valley_time[valley] += h;
+                valley_v_dt[valley] += v_dt;
+
+                let new_r = r + v_dt;
+                while trajectory.len() < points && sample_time(trajectory.len()) <= t + h {
+                    let ts = sample_time(trajectory.len());
+                    trajectory.push((ts, r + (new_r - r) * ((ts - t) / h)));
+                }
+                r = new_r;
 
-            // приводим импульс к зоне
-            p = self.m.brillouin_zone().to_first_bz(p);
+                // так же интерполируем скорость и энергию в заданные моменты
+                while sampled.len() < samples.len() && samples[sampled.len()] <= t + h {
+                    let ts = samples[sampled.len()];
+                    let w = (ts - t) / h;
+                    sampled.push(Sample {
+                        t: ts,
+                        velocity: v + (v_end - v) * w,
+                        energy: e_start + (e_end - e_start) * w,
+                    });
+                }
 
+                for (i, &omega) in sampling.frequencies.iter().enumerate() {
+                    let (sin, cos) = (omega * t).sin_cos();
+                    let (sin_end, cos_end) = (omega * (t + h)).sin_cos();
+                    int_v_cos[i] += (v * cos + v_end * cos_end) * (h / 2.0);
+                    int_v_sin[i] += (v * sin + v_end * sin_end) * (h / 2.0);
+                }
 
-            let mut e = self.m.energy(p);
-            int_e_dt = int_e_dt + e * dt;
-            let dwlo = self.m.optical_scattering(p); // 0, если выпал из минизоны
-            let dwla = self.m.acoustic_scattering(p);
-            wsum += (dwla + dwlo) * dt;
+                int_e_dt += (e_start + e_end) * (h / 2.0);
+                t += h;
+            }
+            if last {
+                break;
+            }
 
-            if wsum > r {
-                r = -rng.uniform().ln();
-                wsum = 0.0;
-                let mut kind = Scattering::Acoustic;
-                if dwlo / (dwla + dwlo) > rng.uniform() {
-                    n_opt += 1; // наращиваем счетчик рассеяний на оптических
-                             // фононах
-                    e -= self.m.optical_energy();
-                    kind = Scattering::Optical
-                } else {
-                    n_ac += 1; // наращиваем счетчик рассеяний на акустических фононах
+            let e = self.m.band_energy(band, p);
+            let mut u = rng.uniform() * gamma;
+            // с вероятностью 1 - total / gamma происходит саморассеяние,
+            // которое не меняет состояние (в том числе в вершине конуса Дирака)
+            if u >= total {
+                n_self += 1;
+                continue;
+            }
+            // разыгрываем механизм рассеяния и конечную зону
+            let mut k = rates.len() - 1;
+            for (i, &rate) in rates.iter().enumerate() {
+                if u < rate {
+                    k = i;
+                    break;
                 }
-                let mut count = 15;
-                let theta = (p.y.atan2(p.x) + 2.0 * PI) % (2.0 * PI);
-                while count > 0 {
-                    let dtheta = 2.0 * PI * rng.uniform(); // случайным образом
-                    // разыгрываем направление квазиимпульса
-                    let new_theta = (theta + dtheta) % (2.0 * PI);
-                    let phase = f.omega.1 * t % (2.0 * PI);
-                    let ps = self.m.momentums(e, new_theta);
-                    if ps.len() > 0 {
-                        p = ps[0];
-                        match kind {
-                            Scattering::Acoustic => {
-                                from_theta_ac.add(theta);
-                                to_theta_ac.add(new_theta);
-                                field_phase_ac.add(phase);
-                            },
-                            Scattering::Optical => {
-                                from_theta_op.add(theta);
-                                to_theta_op.add(new_theta);
-                                field_phase_op.add(phase);
-                            },
-                        };
-                        break;
+                u -= rate;
+            }
+            let (kind, to) = (k / bands, k % bands);
+            let mechanism = &mechanisms[kind];
+            let new_e = e + mechanism.energy_change(m, band, to);
+            let theta = (p.y.atan2(p.x) + 2.0 * PI) % (2.0 * PI);
+            // разыгрываем конечное состояние на изоэнергетическом контуре,
+            // если конечное состояние не найдено, импульс не меняется
+            let new_p = mechanism.final_state(m, band, to, new_e, p, &mut || rng.uniform());
+            if let Some(new_p) = new_p {
+                counts[kind] += 1; // наращиваем счетчик рассеяний
+                p = new_p;
+                if to != band {
+                    if self.m.valley(to) != self.m.valley(band) {
+                        n_iv += 1;
+                    } else {
+                        n_inter += 1;
                     }
-                    // если p существует, то мы правильно
-                    // подобрали угол рассеяния, поэтому выходим из цикла
-                    // если за 15 попыток не нашли решение, выходим из цикла
-                    count -= 1;
                 }
+                band = to;
+                from_theta[kind].add(theta);
+                to_theta[kind].add((p.y.atan2(p.x) + 2.0 * PI) % (2.0 * PI));
+                field_phase[kind].add((f.frequency() * t).rem_euclid(2.0 * PI));
+            } else {
+                failed[kind] += 1;
             }
-
-            t += dt;
         }
-        let n0 = n_ac + n_opt;
-        let average_speed = int_v_dt / t;
+        while trajectory.len() < points {
+            trajectory.push((sample_time(trajectory.len()), r));
+        }
+        let n0: u32 = counts.iter().sum();
+        let n_failed: u32 = failed.iter().sum();
+        let average_speed = r / t;
         let tau = t / (n0 as f64 + 1.0);
         let energy = int_e_dt / t;
 
+        // устаревшие поля acoustic и optical заполняем по виду процесса, а не по имени
+        let processes: Vec<Option<PhononProcess>> =
+            mechanisms.iter().map(|x| x.phonon_process()).collect();
+        let mechanisms: Vec<MechanismSummary> = mechanisms.iter()
+            .zip(counts.into_iter().zip(failed))
+            .zip(from_theta.into_iter().zip(to_theta).zip(field_phase))
+            .map(|((mechanism, (count, failed)), ((from, to), phase))| {
+                MechanismSummary {
+                    name: mechanism.name().to_string(),
+                    count,
+                    failed,
+                    from_theta: from.bins,
+                    to_theta: to.bins,
+                    field_phase: phase.bins,
+                }
+            })
+            .collect();
+        let phonons = |process: PhononProcess| {
+            processes.iter()
+                .position(|&x| x == Some(process))
+                .map(|i| mechanisms[i].clone())
+                .unwrap_or(MechanismSummary {
+                    name: String::new(),
+                    count: 0,
+                    failed: 0,
+                    from_theta: vec![0; n_bins],
+                    to_theta: vec![0; n_bins],
+                    field_phase: vec![0; n_bins],
+                })
+        };
+        let acoustic = phonons(PhononProcess::Acoustic);
+        let optical = phonons(PhononProcess::OpticalEmission);
+
         Summary {
-            average_speed: average_speed,
-            acoustic: n_ac,
-            optical: n_opt,
-            tau: tau,
-            energy: energy,
-            from_theta_ac: from_theta_ac.bins,
-            to_theta_ac: to_theta_ac.bins,
-            from_theta_op: from_theta_op.bins,
-            to_theta_op: to_theta_op.bins,
-            field_phase_ac: field_phase_ac.bins,
-            field_phase_op: field_phase_op.bins,
+            average_speed,
+            acoustic: acoustic.count,
+            optical: optical.count,
+            optical_absorption: phonons(PhononProcess::OpticalAbsorption).count,
+            interband: n_inter,
+            intervalley: n_iv,
+            valley_occupation: valley_time.into_iter().map(|x| x / t).collect(),
+            valley_current: valley_v_dt.into_iter().map(|x| x / t).collect(),
+            displacement: r,
+            trajectory,
+            samples: sampled,
+            harmonics: sampling.frequencies
+                .iter()
+                .zip(int_v_cos.into_iter().zip(int_v_sin))
+                .map(|(&omega, (c, s))| {
+                    let factor = if omega == 0.0 { 1.0 / t } else { 2.0 / t };
+                    (omega, Amplitude::new(c * factor, -s * factor))
+                })
+                .collect(),
+            failed: n_failed,
+            self_scattering: n_self,
+            redrawn: n_redrawn,
+            tau,
+            energy,
+            from_theta_ac: acoustic.from_theta,
+            to_theta_ac: acoustic.to_theta,
+            from_theta_op: optical.from_theta,
+            to_theta_op: optical.to_theta,
+            field_phase_ac: acoustic.field_phase,
+            field_phase_op: optical.field_phase,
+            mechanisms,
         }
     }
 }
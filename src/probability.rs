@@ -2,7 +2,18 @@
 use material::Material;
 use linal::Vec2;
 
+/// Contribution of contour element `dp` at point `p`. Contour degenerated into point
+/// (e.g. tip of Dirac cone, where gradient vanishes) contributes nothing.
+fn element<T: Material>(m: &T, dp: Vec2, p: Vec2) -> f64 {
+    let dl = dp.len();
+    if dl == 0.0 {
+        return 0.0;
+    }
+    dl / m.energy_gradient(p).len()
+}
+
 /// Calculates $\int\limits\_{BZ} \delta(E(p)-E) d\^{2} p$
+#[allow(clippy::needless_range_loop)]
 pub fn probability<T: Material>(energy: f64, m: &T, error: f64) -> f64 {
     use std::f64::consts::PI;
     use std::cmp::min;
@@ -23,7 +34,7 @@ pub fn probability<T: Material>(energy: f64, m: &T, error: f64) -> f64 {
             let curr = m.momentums(energy, theta);
             let l = min(curr.len(), prev.len());
             for j in 0..l {
-                new += (curr[j] - prev[j]).len() / m.energy_gradient(curr[j]).len();
+                new += element(m, curr[j] - prev[j], curr[j]);
             }
 
             // find endpoints
@@ -37,8 +48,7 @@ pub fn probability<T: Material>(energy: f64, m: &T, error: f64) -> f64 {
                         dtheta /= 2.0;
                     }
                 }
-                new += (curr[i] - m.momentums(energy, theta)[i]).len() /
-                       m.energy_gradient(curr[i]).len();
+                new += element(m, curr[i] - m.momentums(energy, theta)[i], curr[i]);
             }
 
 
@@ -52,8 +62,7 @@ pub fn probability<T: Material>(energy: f64, m: &T, error: f64) -> f64 {
                         dtheta /= 2.0;
                     }
                 }
-                new += (prev[i] - m.momentums(energy, theta)[i]).len() /
-                       m.energy_gradient(prev[i]).len();
+                new += element(m, prev[i] - m.momentums(energy, theta)[i], prev[i]);
             }
 
             prev = curr;
@@ -1,5 +1,26 @@
+//! Pseudo-random number generators used by particles and ensembles
+
+/// Source of random numbers, which can be split into independent streams
+pub trait RandomGenerator: Clone {
+    /// Creates generator from seed
+    fn from_seed(seed: u32) -> Self;
+    /// Next random number uniformly distributed over all `u32` values
+    fn rand(&mut self) -> u32;
+    /// Random number uniformly distributed in $[0, 1]$
+    fn uniform(&mut self) -> f64 {
+        self.rand() as f64 / u32::MAX as f64
+    }
+    /// Independent generator with number `id`, derived from seed of this one
+    fn stream(&self, id: u32) -> Self;
+    /// Skips next `n` random numbers
+    fn skip(&mut self, n: u64);
+}
+
+#[derive(Clone)]
 /// Xorshift128 pseudo-random number generator
 pub struct Rng {
+    /// 64-bit key, from which state is mixed and streams are derived
+    key: u64,
     x: u32,
     y: u32,
     z: u32,
@@ -7,15 +28,30 @@ pub struct Rng {
 }
 
 impl Rng {
+    /// Creates generator, all four words of state of which are mixed from `seed`
     pub fn new(seed: u32) -> Rng {
+        Rng::with_key(seed as u64)
+    }
+
+    fn with_key(key: u64) -> Rng {
+        let a = splitmix(key);
+        let b = splitmix(a);
         Rng {
-            x: seed,
-            y: 362_436_069,
-            z: 521_288_629,
-            w: 88_675_123,
+            key,
+            x: a as u32,
+            y: (a >> 32) as u32,
+            z: b as u32,
+            // state of xorshift should not be zero
+            w: (b >> 32) as u32 | 1,
         }
     }
-    pub fn rand(&mut self) -> u32 {
+}
+
+impl RandomGenerator for Rng {
+    fn from_seed(seed: u32) -> Rng {
+        Rng::new(seed)
+    }
+    fn rand(&mut self) -> u32 {
         let t = self.x ^ (self.x << 11);
         self.x = self.y;
         self.y = self.z;
@@ -23,9 +59,198 @@ impl Rng {
         self.w = self.w ^ (self.w >> 19) ^ t ^ (t >> 8);
         self.w
     }
-    pub fn uniform(&mut self) -> f64 {
-        self.rand() as f64 / u32::max_value() as f64
+    /// Key of stream is mixed from full 64-bit key of parent, like in `Philox::stream`
+    fn stream(&self, id: u32) -> Rng {
+        Rng::with_key(splitmix(splitmix(self.key).wrapping_add(id as u64)))
+    }
+    /// Xorshift has no cheap jump-ahead, so numbers are generated one by one
+    fn skip(&mut self, n: u64) {
+        for _ in 0..n {
+            self.rand();
+        }
+    }
+}
+
+const PHILOX_M0: u32 = 0xD251_1F53;
+const PHILOX_M1: u32 = 0xCD9E_8D57;
+const PHILOX_W0: u32 = 0x9E37_79B9;
+const PHILOX_W1: u32 = 0xBB67_AE85;
+
+#[derive(Clone)]
+/// Philox4x32-10 counter-based pseudo-random number generator.
+///
+/// Every 128-bit counter is encrypted into four random numbers. Low half of counter
+/// is number of block in stream, so `skip` costs nothing. Key of stream is mixed from key
+/// of its parent and number of stream, so streams and their substreams don't overlap
+/// unless 64-bit keys collide.
+pub struct Philox {
+    key: [u32; 2],
+    counter: [u32; 4],
+    buffer: [u32; 4],
+    index: usize,
+}
+
+impl Philox {
+    pub fn new(key: u64) -> Philox {
+        let mut rng = Philox {
+            key: [key as u32, (key >> 32) as u32],
+            counter: [0; 4],
+            buffer: [0; 4],
+            index: 0,
+        };
+        rng.buffer = rng.block();
+        rng
+    }
+
+    fn block(&self) -> [u32; 4] {
+        let mut ctr = self.counter;
+        let mut key = self.key;
+        for round in 0..10 {
+            if round > 0 {
+                key[0] = key[0].wrapping_add(PHILOX_W0);
+                key[1] = key[1].wrapping_add(PHILOX_W1);
+            }
+            let p0 = PHILOX_M0 as u64 * ctr[0] as u64;
+            let p1 = PHILOX_M1 as u64 * ctr[2] as u64;
+            ctr = [(p1 >> 32) as u32 ^ ctr[1] ^ key[0],
+                   p1 as u32,
+                   (p0 >> 32) as u32 ^ ctr[3] ^ key[1],
+                   p0 as u32];
+        }
+        ctr
+    }
+
+    fn block_number(&self) -> u64 {
+        self.counter[0] as u64 | (self.counter[1] as u64) << 32
+    }
+
+    fn set_block_number(&mut self, n: u64) {
+        self.counter[0] = n as u32;
+        self.counter[1] = (n >> 32) as u32;
+        self.buffer = self.block();
+    }
+}
+
+impl RandomGenerator for Philox {
+    fn from_seed(seed: u32) -> Philox {
+        Philox::new(seed as u64)
+    }
+    fn rand(&mut self) -> u32 {
+        if self.index == 4 {
+            let n = self.block_number().wrapping_add(1);
+            self.set_block_number(n);
+            self.index = 0;
+        }
+        self.index += 1;
+        self.buffer[self.index - 1]
+    }
+    fn stream(&self, id: u32) -> Philox {
+        let key = self.key[0] as u64 | (self.key[1] as u64) << 32;
+        Philox::new(splitmix(splitmix(key).wrapping_add(id as u64)))
     }
+    fn skip(&mut self, n: u64) {
+        let position = self.index as u64 + n;
+        let n = self.block_number().wrapping_add(position / 4);
+        self.set_block_number(n);
+        self.index = (position % 4) as usize;
+    }
+}
+
+/// Splitmix64 mixing function, bijection of 64-bit numbers
+fn splitmix(z: u64) -> u64 {
+    let mut z = z.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+/// Derives seed of `index`-th independent stream from `master` seed.
+///
+/// Uses splitmix-like mixing, so neighbouring indices give uncorrelated seeds.
+pub fn derive_seed(master: u32, index: u32) -> u32 {
+    let z = splitmix((master as u64) << 32 | index as u64);
+    (z ^ (z >> 32)) as u32
+}
+
+#[test]
+fn test_derive_seed() {
+    assert_eq!(derive_seed(42, 7), derive_seed(42, 7));
+    let mut seeds: Vec<u32> = (0..1000).map(|i| derive_seed(42, i)).collect();
+    seeds.sort();
+    seeds.dedup();
+    assert_eq!(seeds.len(), 1000);
+    assert!(derive_seed(42, 0) != derive_seed(43, 0));
+}
+
+#[test]
+fn test_rng_streams() {
+    // 32-bit seeds of so many streams would collide
+    let rng = Rng::new(42);
+    let mut keys: Vec<u64> = (0..200_000).map(|i| rng.stream(i).key).collect();
+    keys.sort();
+    keys.dedup();
+    assert_eq!(keys.len(), 200_000);
+    let mut a = rng.stream(1);
+    let mut b = rng.stream(1).stream(0);
+    assert!((0..4).map(|_| a.rand()).ne((0..4).map(|_| b.rand())));
+}
+
+#[test]
+fn test_philox_known_answer() {
+    let mut rng = Philox::new(0);
+    let block: Vec<u32> = (0..4).map(|_| rng.rand()).collect();
+    assert_eq!(block, vec![0x6627_e8d5, 0xe169_c58d, 0xbc57_ac4c, 0x9b00_dbd8]);
+}
+
+#[test]
+fn test_philox_skip() {
+    let mut a = Philox::new(2017);
+    let mut b = a.clone();
+    a.rand();
+    for _ in 0..1001 {
+        b.rand();
+    }
+    a.skip(1000);
+    assert_eq!(a.rand(), b.rand());
+}
+
+#[test]
+fn test_philox_streams() {
+    let rng = Philox::new(2017);
+    let mut a = rng.stream(1);
+    let mut b = rng.stream(2);
+    let mut c = rng.stream(1);
+    let xs: Vec<u32> = (0..100).map(|_| a.rand()).collect();
+    let ys: Vec<u32> = (0..100).map(|_| b.rand()).collect();
+    let zs: Vec<u32> = (0..100).map(|_| c.rand()).collect();
+    assert!(xs != ys);
+    assert_eq!(xs, zs);
+}
+
+#[test]
+fn test_philox_substreams() {
+    let first = |mut rng: Philox| (0..4).map(|_| rng.rand()).collect::<Vec<u32>>();
+    let (a, b) = (Philox::new(1), Philox::new(2));
+    let mut blocks = vec![first(a.clone()), first(a.stream(0)), first(b.clone()), first(b.stream(0))];
+    for i in 0..8 {
+        for j in 0..8 {
+            blocks.push(first(a.stream(i).stream(j)));
+            blocks.push(first(b.stream(i).stream(j)));
+        }
+    }
+    let n = blocks.len();
+    blocks.sort();
+    blocks.dedup();
+    assert_eq!(blocks.len(), n);
+}
+
+#[test]
+fn test_rng_seeding() {
+    // neighbouring seeds change about half of bits of the first number
+    let bits: u32 = (0..1000u32)
+        .map(|i| (Rng::new(i).rand() ^ Rng::new(i + 1).rand()).count_ones())
+        .sum();
+    assert!((bits as f64 / 1000.0 - 16.0).abs() < 1.0, "{}", bits);
 }
 
 #[test]
@@ -40,8 +265,8 @@ fn test_uniform() {
         let p = (r / width).floor() as usize;
         distrib[p] += 1;
     }
-    for i in 0..intervals {
-        let density = distrib[i] as f64 / samples as f64 / width;
+    for &count in &distrib {
+        let density = count as f64 / samples as f64 / width;
         let stdev = ((1.0 - width) / samples as f64 / width).sqrt();
         assert!((density - 1.0).abs() < 3.0 * stdev);
     }
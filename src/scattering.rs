@@ -0,0 +1,782 @@
+//! Scattering mechanisms, which move electron between states of material
+use std::f64::consts::PI;
+use material::MultiBand;
+use linal::Vec2;
+
+/// Number of directions, in which isoenergy contour is tabulated to sample final state
+const CONTOUR_POINTS: usize = 64;
+
+/// Ratio of bound of density inside interval of contour table to its maximum in tabulated points
+const DENSITY_MARGIN: f64 = 1.5;
+
+/// Maximal number of rejected directions while sampling final state
+const MAX_REJECTIONS: usize = 1000;
+
+/// Number of points along each basis vector of zone to estimate maximal scattering probability
+const RATE_GRID: usize = 32;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+/// Phonon processes, which are counted in separate fields of `Summary`
+pub enum PhononProcess {
+    Acoustic,
+    OpticalEmission,
+    OpticalAbsorption,
+}
+
+/// Process, which scatters electron from band `from` to band `to` with given probability
+/// per unit time, changing its energy by fixed amount.
+pub trait ScatteringMechanism {
+    /// Name of mechanism in summaries
+    fn name(&self) -> &str;
+    /// Phonon process, which mechanism is, `None` by default
+    fn phonon_process(&self) -> Option<PhononProcess> {
+        None
+    }
+    /// Scattering probability from momentum `p` in band `from` to band `to`
+    fn rate(&self, m: &dyn MultiBand, from: usize, to: usize, p: Vec2) -> f64;
+    /// Change of electron energy in transition from band `from` to band `to`
+    fn energy_change(&self, m: &dyn MultiBand, from: usize, to: usize) -> f64;
+    /// Squared matrix element $|M(p, p')|\^2$ of transition from `p` in band `from` to `q`
+    /// in band `to` up to constant factor, isotropic by default
+    fn matrix_element(&self,
+                      _m: &dyn MultiBand,
+                      _from: usize,
+                      _to: usize,
+                      _p: Vec2,
+                      _q: Vec2)
+                      -> f64 {
+        1.0
+    }
+    /// Samples final momentum with given `energy` in band `to` after scattering from `p`
+    /// in band `from`, drawing random numbers from `uniform`. Returns `None` if no state
+    /// was found. By default final state is distributed along isoenergy contour according
+    /// to `matrix_element`, see `contour_final_state`.
+    fn final_state(&self,
+                   m: &dyn MultiBand,
+                   from: usize,
+                   to: usize,
+                   energy: f64,
+                   p: Vec2,
+                   uniform: &mut dyn FnMut() -> f64)
+                   -> Option<Vec2> {
+        let weight = |q| self.matrix_element(m, from, to, p, q);
+        contour_final_state(m, to, energy, p, &weight, uniform)
+    }
+}
+
+/// Samples final state on isoenergy contour $E\_{to}(p') = E$ with probability density
+/// $w(p') / |\nabla E(p')|$ per unit length of contour.
+///
+/// In polar coordinates this density is $w(p') p'\^2 / |p' \cdot \nabla E(p')|$ per unit angle
+/// for every root $p'$ on ray, so all branches of contour crossing the ray are taken into
+/// account. Density is tabulated at ends and middles of `CONTOUR_POINTS` intervals of directions
+/// evenly spaced from direction of `p`, its maximum on every interval times `DENSITY_MARGIN` bounds
+/// density inside the interval. Interval is drawn with probability proportional to its bound,
+/// direction is drawn uniformly inside the interval and accepted with probability density / bound,
+/// so sampling is exact while density doesn't exceed the bound. Then root on this direction is
+/// chosen with probability proportional to its density. Returns `None` if the contour is empty or
+/// `MAX_REJECTIONS` directions are rejected.
+pub fn contour_final_state(m: &dyn MultiBand,
+                           to: usize,
+                           energy: f64,
+                           p: Vec2,
+                           weight: &dyn Fn(Vec2) -> f64,
+                           uniform: &mut dyn FnMut() -> f64)
+                           -> Option<Vec2> {
+    let angle = |u: f64| (2.0 * PI * u, 2.0 * PI);
+    warped_contour_final_state(m, to, energy, p, weight, &angle, uniform)
+}
+
+/// The same as `contour_final_state`, but directions are $\theta\_p + \phi(u)$ for evenly spaced
+/// $u \in [0, 1)$, where `angle` gives $\phi(u)$ and $d\phi / du$.
+///
+/// Density is tabulated by $u$, that is multiplied by $d\phi / du$, so when $\phi(u)$ is inverse
+/// distribution function of scattering angle for circular contour, tabulated density is
+/// nearly constant and sharp peaks of $w$ are sampled exactly.
+pub fn warped_contour_final_state(m: &dyn MultiBand,
+                                  to: usize,
+                                  energy: f64,
+                                  p: Vec2,
+                                  weight: &dyn Fn(Vec2) -> f64,
+                                  angle: &dyn Fn(f64) -> (f64, f64),
+                                  uniform: &mut dyn FnMut() -> f64)
+                                  -> Option<Vec2> {
+    let theta = p.y.atan2(p.x);
+    let density = |q: Vec2| {
+        let projection = q.dot(m.band_energy_gradient(to, q)).abs();
+        if projection > 0.0 { weight(q) * q.dot(q) / projection } else { 0.0 }
+    };
+    let roots = |i: f64| -> Vec<(Vec2, f64)> {
+        let (phi, derivative) = angle(i / CONTOUR_POINTS as f64);
+        m.band_momentums(to, energy, (theta + phi).rem_euclid(2.0 * PI))
+            .into_iter()
+            .map(|q| (q, density(q) * derivative))
+            .collect()
+    };
+    let total = |roots: &[(Vec2, f64)]| roots.iter().map(|x| x.1).sum::<f64>();
+
+    // directions $u = 0, 1/2, 1, \ldots$ in units of interval, $u = 0$ and $u = n$ coincide
+    let values: Vec<f64> = (0..2 * CONTOUR_POINTS).map(|j| total(&roots(j as f64 / 2.0))).collect();
+    let mut bounds = Vec::with_capacity(CONTOUR_POINTS);
+    let mut cumulative = Vec::with_capacity(CONTOUR_POINTS);
+    let mut sum = 0.0;
+    for i in 0..CONTOUR_POINTS {
+        let ends = values[2 * i].max(values[(2 * i + 2) % values.len()]);
+        let bound = DENSITY_MARGIN * ends.max(values[2 * i + 1]);
+        sum += bound;
+        bounds.push(bound);
+        cumulative.push(sum);
+    }
+    if sum <= 0.0 {
+        return None;
+    }
+    for _ in 0..MAX_REJECTIONS {
+        let u = uniform() * sum;
+        let i = cumulative.iter().position(|&c| u < c).unwrap_or(CONTOUR_POINTS - 1);
+        let candidates = roots(i as f64 + uniform());
+        let density = total(&candidates);
+        if uniform() * bounds[i] >= density {
+            continue;
+        }
+        let mut u = uniform() * density;
+        for &(q, d) in &candidates {
+            if u < d {
+                return Some(q);
+            }
+            u -= d;
+        }
+        return candidates.iter().rev().find(|x| x.1 > 0.0).map(|x| x.0);
+    }
+    None
+}
+
+/// Integral $\oint w(p') dl / |\nabla E(p')|$ along isoenergy contour $E\_{to}(p') = E$ by
+/// midpoint rule in `CONTOUR_POINTS` directions $\theta\_p + \phi(u)$, see
+/// `warped_contour_final_state` for `angle`
+pub fn contour_integral(m: &dyn MultiBand,
+                        to: usize,
+                        energy: f64,
+                        p: Vec2,
+                        weight: &dyn Fn(Vec2) -> f64,
+                        angle: &dyn Fn(f64) -> (f64, f64))
+                        -> f64 {
+    let theta = p.y.atan2(p.x);
+    let mut sum = 0.0;
+    for i in 0..CONTOUR_POINTS {
+        let (phi, derivative) = angle((i as f64 + 0.5) / CONTOUR_POINTS as f64);
+        for q in m.band_momentums(to, energy, (theta + phi).rem_euclid(2.0 * PI)) {
+            let projection = q.dot(m.band_energy_gradient(to, q)).abs();
+            if projection > 0.0 {
+                sum += weight(q) * q.dot(q) / projection * derivative;
+            }
+        }
+    }
+    sum / CONTOUR_POINTS as f64
+}
+
+/// Emission of optical phonon, given by `MultiBand::band_optical_scattering`
+pub struct Optical;
+
+impl ScatteringMechanism for Optical {
+    fn name(&self) -> &str {
+        "optical emission"
+    }
+    fn phonon_process(&self) -> Option<PhononProcess> {
+        Some(PhononProcess::OpticalEmission)
+    }
+    fn rate(&self, m: &dyn MultiBand, from: usize, to: usize, p: Vec2) -> f64 {
+        m.band_optical_scattering(from, to, p)
+    }
+    fn energy_change(&self, m: &dyn MultiBand, from: usize, to: usize) -> f64 {
+        -m.band_optical_energy(from, to)
+    }
+}
+
+/// Absorption of optical phonon, given by `MultiBand::band_optical_absorption`
+pub struct OpticalAbsorption;
+
+impl ScatteringMechanism for OpticalAbsorption {
+    fn name(&self) -> &str {
+        "optical absorption"
+    }
+    fn phonon_process(&self) -> Option<PhononProcess> {
+        Some(PhononProcess::OpticalAbsorption)
+    }
+    fn rate(&self, m: &dyn MultiBand, from: usize, to: usize, p: Vec2) -> f64 {
+        m.band_optical_absorption(from, to, p)
+    }
+    fn energy_change(&self, m: &dyn MultiBand, from: usize, to: usize) -> f64 {
+        m.band_optical_energy(from, to)
+    }
+}
+
+/// Elastic scattering by acoustic phonons, given by `MultiBand::band_acoustic_scattering`
+pub struct Acoustic;
+
+impl ScatteringMechanism for Acoustic {
+    fn name(&self) -> &str {
+        "acoustic"
+    }
+    fn phonon_process(&self) -> Option<PhononProcess> {
+        Some(PhononProcess::Acoustic)
+    }
+    fn rate(&self, m: &dyn MultiBand, from: usize, to: usize, p: Vec2) -> f64 {
+        m.band_acoustic_scattering(from, to, p)
+    }
+    fn energy_change(&self, _m: &dyn MultiBand, _from: usize, _to: usize) -> f64 {
+        0.0
+    }
+}
+
+/// Emission of intervalley phonon, given by `MultiBand::intervalley_scattering`
+pub struct Intervalley;
+
+impl ScatteringMechanism for Intervalley {
+    fn name(&self) -> &str {
+        "intervalley"
+    }
+    fn rate(&self, m: &dyn MultiBand, from: usize, to: usize, p: Vec2) -> f64 {
+        m.intervalley_scattering(from, to, p)
+    }
+    fn energy_change(&self, m: &dyn MultiBand, from: usize, to: usize) -> f64 {
+        -m.intervalley_energy(from, to)
+    }
+}
+
+#[derive(Clone)]
+/// Elastic intraband scattering by ionized impurities in plane of two-dimensional electron
+/// gas with potential $V(q) = 2 \pi / \kappa (q + q\_s)$ screened by the gas itself,
+/// $q = |p - p'|$, $q\_s = 1 / \lambda$.
+///
+/// Probability $W = n\_i / 2 \pi \oint |V(q)|\^2 dl / |\nabla E(p')|$ is integrated along
+/// isoenergy contour, see `contour_integral`, so it doesn't matter where band minimum is.
+/// Final state is sampled along contour with $|M|\^2 \propto 1 / (|p - p'| + q\_s)\^2$.
+/// Directions of contour are warped by inverse distribution function of scattering angle
+/// on the circle, see `Impurities::scattering_angle`, so forward peak is resolved for any
+/// screening.
+pub struct Impurities {
+    /// sheet density of impurities $n\_i$
+    pub density: f64,
+    /// screening length $\lambda$
+    pub screening_length: f64,
+    /// dielectric permittivity $\kappa$
+    pub permittivity: f64,
+}
+
+impl Impurities {
+    pub fn new(density: f64, screening_length: f64, permittivity: f64) -> Impurities {
+        Impurities {
+            density,
+            screening_length,
+            permittivity,
+        }
+    }
+
+    /// Scattering angle on the circle of radius `k` with density
+    /// $\propto 1 / (2 k\^2 (1 - \cos \phi) + q\_s\^2)$ for uniform random number `u`
+    /// and its derivative by `u`. The density differs from $|M|\^2$ on the circle at most
+    /// twice.
+    pub fn scattering_angle(&self, k: f64, u: f64) -> (f64, f64) {
+        let qs = 1.0 / self.screening_length;
+        let ratio = qs / (4.0 * k * k + qs * qs).sqrt();
+        let x = PI * (u - 0.5);
+        let (sin, cos) = x.sin_cos();
+        (2.0 * (ratio * x.tan()).atan(),
+         2.0 * PI * ratio / (cos * cos + ratio * ratio * sin * sin))
+    }
+}
+
+impl ScatteringMechanism for Impurities {
+    fn name(&self) -> &str {
+        "impurity"
+    }
+    fn rate(&self, m: &dyn MultiBand, from: usize, to: usize, p: Vec2) -> f64 {
+        if from != to {
+            return 0.0;
+        }
+        let weight = |q| self.matrix_element(m, from, to, p, q);
+        let angle = |u| self.scattering_angle(p.len(), u);
+        let strength = 2.0 * PI / self.permittivity;
+        self.density * strength * strength / (2.0 * PI) *
+        contour_integral(m, to, m.band_energy(from, p), p, &weight, &angle)
+    }
+    fn energy_change(&self, _m: &dyn MultiBand, _from: usize, _to: usize) -> f64 {
+        0.0
+    }
+    fn matrix_element(&self,
+                      _m: &dyn MultiBand,
+                      _from: usize,
+                      _to: usize,
+                      p: Vec2,
+                      q: Vec2)
+                      -> f64 {
+        let qs = 1.0 / self.screening_length;
+        1.0 / ((p - q).len() + qs).powi(2)
+    }
+    fn final_state(&self,
+                   m: &dyn MultiBand,
+                   from: usize,
+                   to: usize,
+                   energy: f64,
+                   p: Vec2,
+                   uniform: &mut dyn FnMut() -> f64)
+                   -> Option<Vec2> {
+        let weight = |q| self.matrix_element(m, from, to, p, q);
+        let angle = |u| self.scattering_angle(p.len(), u);
+        warped_contour_final_state(m, to, energy, p, &weight, &angle, uniform)
+    }
+}
+
+/// Probability of scattering by `mechanism` from momentum `p` in band `from` to band `to`.
+///
+/// Panics if it is not finite.
+pub fn checked_rate(mechanism: &dyn ScatteringMechanism,
+                    m: &dyn MultiBand,
+                    from: usize,
+                    to: usize,
+                    p: Vec2)
+                    -> f64 {
+    let rate = mechanism.rate(m, from, to, p);
+    assert!(rate.is_finite(),
+            "mechanism {} gives invalid probability {} from band {} to {} at {:?}",
+            mechanism.name(),
+            rate,
+            from,
+            to,
+            p);
+    rate
+}
+
+/// Estimates maximum of total probability of scattering by `mechanisms` over all bands
+/// of material on uniform grid in brillouin zone.
+///
+/// Panics if some mechanism gives not finite probability.
+pub fn max_rate(m: &dyn MultiBand, mechanisms: &[Box<dyn ScatteringMechanism>]) -> f64 {
+    let bands = m.bands();
+    let mut max: f64 = 0.0;
+    for i in 0..RATE_GRID {
+        for j in 0..RATE_GRID {
+            let u = (i as f64 + 0.5) / RATE_GRID as f64;
+            let v = (j as f64 + 0.5) / RATE_GRID as f64;
+            let p = m.zone().sample(u, v);
+            for from in 0..bands {
+                let total: f64 = mechanisms.iter()
+                    .map(|x| (0..bands).map(|to| checked_rate(&**x, m, from, to, p)).sum::<f64>())
+                    .sum();
+                max = max.max(total);
+            }
+        }
+    }
+    max
+}
+
+/// Emission and absorption of optical phonons and acoustic phonons
+pub fn phonon_mechanisms() -> Vec<Box<dyn ScatteringMechanism>> {
+    vec![Box::new(Optical), Box::new(OpticalAbsorption), Box::new(Acoustic)]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use material::{BrillouinZone, Material};
+    use materials::{Parabolic, Phonons};
+    use rng::{RandomGenerator, Rng};
+    use test_support::with_mechanisms;
+
+    #[test]
+    fn test_phonon_mechanisms() {
+        let bz = BrillouinZone::new(Vec2::new(-1.0, -1.0),
+                                    Vec2::new(1.0, -1.0),
+                                    Vec2::new(-1.0, 1.0));
+        let m = &Parabolic::new(10.0, bz, Phonons::new(5e-3, 0.2, 0.3));
+        let p = Vec2::new(0.3, 0.1);
+        let (optical, acoustic) = (Optical, Acoustic);
+        assert_eq!(optical.rate(m, 0, 0, p), m.optical_scattering(p));
+        assert_eq!(acoustic.rate(m, 0, 0, p), m.acoustic_scattering(p));
+        assert_eq!(optical.energy_change(m, 0, 0), -5e-3);
+        assert_eq!(Intervalley.rate(m, 0, 0, p), 0.0);
+
+        let mut rng = Rng::new(3);
+        let energy = m.energy(p);
+        let q = acoustic.final_state(m, 0, 0, energy, p, &mut || rng.uniform()).unwrap();
+        assert!((m.energy(q) - energy).abs() < 1e-12);
+        assert!(acoustic.final_state(m, 0, 0, 1.0, p, &mut || rng.uniform()).is_none());
+    }
+
+    /// Integral $\int\_0\^{\phi\_0} f(\phi) d\phi / (2 k \sin(\phi / 2) + q\_s)\^2$ over
+    /// scattering angle on circle of radius $k$
+    fn angular_integral(k: f64, qs: f64, limit: f64, f: &dyn Fn(f64) -> f64) -> f64 {
+        let n = 200000;
+        let h = limit / n as f64;
+        (0..n)
+            .map(|i| {
+                let phi = h * (i as f64 + 0.5);
+                f(phi) * h / (2.0 * k * (phi / 2.0).sin() + qs).powi(2)
+            })
+            .sum()
+    }
+
+    /// Shift of minimum of `Shifted` band from origin
+    const SHIFT: Vec2 = Vec2 { x: 0.1, y: -0.05 };
+
+    /// Parabolic band with minimum at `SHIFT`, which is inside of isoenergy contours
+    struct Shifted {
+        m: Parabolic,
+    }
+
+    impl Material for Shifted {
+        fn energy(&self, p: Vec2) -> f64 {
+            self.m.energy(p - SHIFT)
+        }
+        fn energy_gradient(&self, p: Vec2) -> Vec2 {
+            self.m.energy_gradient(p - SHIFT)
+        }
+        fn min_energy(&self) -> f64 {
+            self.m.min_energy()
+        }
+        fn max_energy(&self) -> f64 {
+            self.m.max_energy()
+        }
+        fn brillouin_zone(&self) -> &BrillouinZone {
+            self.m.brillouin_zone()
+        }
+        fn optical_energy(&self) -> f64 {
+            self.m.optical_energy()
+        }
+        fn optical_scattering(&self, p: Vec2) -> f64 {
+            self.m.optical_scattering(p - SHIFT)
+        }
+        fn acoustic_scattering(&self, p: Vec2) -> f64 {
+            self.m.acoustic_scattering(p - SHIFT)
+        }
+    }
+
+    #[test]
+    fn test_impurities() {
+        let bz = BrillouinZone::new(Vec2::new(-1.0, -1.0),
+                                    Vec2::new(1.0, -1.0),
+                                    Vec2::new(-1.0, 1.0));
+        let m = &Parabolic::new(10.0, bz, Phonons::new(5e-3, 0.2, 0.3));
+        let impurities = Impurities::new(1e-3, 20.0, 10.0);
+        let p = Vec2::new(0.3, 0.1);
+        let (k, qs) = (p.len(), 0.05);
+        // probability of parabolic band by direct integration over scattering angle
+        let integral = 2.0 * angular_integral(k, qs, PI, &|_| 1.0);
+        let expected = 1e-3 * 10.0 * (2.0 * PI / 10.0).powi(2) * integral / (2.0 * PI);
+        assert!((impurities.rate(m, 0, 0, p) - expected).abs() < 1e-3 * expected);
+        assert_eq!(impurities.rate(m, 0, 1, p), 0.0);
+        assert_eq!(impurities.energy_change(m, 0, 0), 0.0);
+
+        // scattering is forward peaked
+        let mut rng = Rng::new(5);
+        let energy = m.energy(p);
+        let n = 20000;
+        let mut cos = 0.0;
+        for _ in 0..n {
+            let q = impurities.final_state(m, 0, 0, energy, p, &mut || rng.uniform()).unwrap();
+            assert!((m.energy(q) - energy).abs() < 1e-12);
+            cos += p.dot(q) / (k * k) / n as f64;
+        }
+        let expected = angular_integral(k, qs, PI, &f64::cos) /
+                       angular_integral(k, qs, PI, &|_| 1.0);
+        assert!((cos - expected).abs() < 1e-2, "{} {}", cos, expected);
+        assert!(cos > 0.8);
+    }
+
+    #[test]
+    fn test_shifted_minimum() {
+        let bz = BrillouinZone::new(Vec2::new(-1.0, -1.0),
+                                    Vec2::new(1.0, -1.0),
+                                    Vec2::new(-1.0, 1.0));
+        let m = &Parabolic::new(10.0, bz.clone(), Phonons::new(5e-3, 0.2, 0.3));
+        let shifted = &Shifted { m: Parabolic::new(10.0, bz, Phonons::new(5e-3, 0.2, 0.3)) };
+        let impurities = Impurities::new(1e-3, 20.0, 10.0);
+        // probability depends on momentum measured from minimum of band
+        let p = Vec2::new(0.3, 0.1);
+        let expected = impurities.rate(m, 0, 0, p);
+        let rate = impurities.rate(shifted, 0, 0, p + SHIFT);
+        assert!((rate - expected).abs() < 1e-3 * expected, "{} {}", rate, expected);
+    }
+
+    #[test]
+    fn test_weak_screening() {
+        let bz = BrillouinZone::new(Vec2::new(-1.0, -1.0),
+                                    Vec2::new(1.0, -1.0),
+                                    Vec2::new(-1.0, 1.0));
+        let m = &Parabolic::new(10.0, bz, Phonons::new(5e-3, 0.2, 0.3));
+        // $q\_s / 2 k = 5 \cdot 10\^{-3}$, peak is much narrower than interval of table
+        let impurities = Impurities::new(1e-3, 1000.0 / 3.0, 10.0);
+        let p = Vec2::new(0.3, 0.0);
+        let (k, qs): (f64, f64) = (0.3, 3e-3);
+        let energy = m.energy(p);
+        let limits = [qs / (4.0 * k), qs / k, 4.0 * qs / k, 0.3, 2.0];
+        let mut rng = Rng::new(11);
+        let n = 20000;
+        let mut counts = vec![0; limits.len()];
+        let mut cos = 0.0;
+        for _ in 0..n {
+            let q = impurities.final_state(m, 0, 0, energy, p, &mut || rng.uniform()).unwrap();
+            let phi = q.y.atan2(q.x);
+            for (c, &limit) in counts.iter_mut().zip(&limits) {
+                if phi.abs() < limit {
+                    *c += 1;
+                }
+            }
+            cos += phi.cos() / n as f64;
+        }
+        for (&c, &limit) in counts.iter().zip(&limits) {
+            let expected = angular_integral(k, qs, limit, &|_| 1.0) /
+                           angular_integral(k, qs, PI, &|_| 1.0);
+            let sigma = (expected * (1.0 - expected) / n as f64).sqrt();
+            let fraction = c as f64 / n as f64;
+            assert!((fraction - expected).abs() < 4.0 * sigma + 1e-4,
+                    "{} {} {}",
+                    limit,
+                    fraction,
+                    expected);
+        }
+        let expected = angular_integral(k, qs, PI, &f64::cos) /
+                       angular_integral(k, qs, PI, &|_| 1.0);
+        assert!((cos - expected).abs() < 2e-3, "{} {}", cos, expected);
+    }
+
+    #[test]
+    fn test_exact_sampling() {
+        let bz = BrillouinZone::new(Vec2::new(-1.0, -1.0),
+                                    Vec2::new(1.0, -1.0),
+                                    Vec2::new(-1.0, 1.0));
+        let m = &Parabolic::new(10.0, bz, Phonons::new(5e-3, 0.2, 0.3));
+        let p = Vec2::new(0.1, 0.2);
+        let energy = m.energy(p);
+        // offset of direction inside interval of table
+        let offset = |q: Vec2| {
+            let phi = (q.y.atan2(q.x) - p.y.atan2(p.x)).rem_euclid(2.0 * PI);
+            (phi / (2.0 * PI) * CONTOUR_POINTS as f64).fract()
+        };
+        // weight peaks in the middle of every interval, so that $s = |t - 1/2|$ has density
+        // $\propto 11 - 20 s$ and $\langle s \rangle = 13 / 72$, while it is $1 / 4$ for
+        // uniform direction inside interval
+        let weight = |q: Vec2| 11.0 - 20.0 * (offset(q) - 0.5).abs();
+        let mut rng = Rng::new(13);
+        let n = 20000;
+        let mut mean = 0.0;
+        for _ in 0..n {
+            let q = contour_final_state(m, 0, energy, p, &weight, &mut || rng.uniform()).unwrap();
+            mean += (offset(q) - 0.5).abs() / n as f64;
+        }
+        assert!((mean - 13.0 / 72.0).abs() < 3e-3, "{}", mean);
+    }
+
+    #[test]
+    fn test_anisotropic_final_state() {
+        use materials::AnisotropicParabolic;
+
+        let bz = BrillouinZone::new(Vec2::new(-1.0, -1.0),
+                                    Vec2::new(1.0, -1.0),
+                                    Vec2::new(-1.0, 1.0));
+        let m = &AnisotropicParabolic::new((5.0, 20.0), 0.0, bz, Phonons::new(5e-3, 0.2, 0.3));
+        let p = Vec2::new(0.1, 0.1);
+        let energy = m.energy(p);
+        // final states are uniform in angle of $(p\_x / \sqrt{m\_x}, p\_y / \sqrt{m\_y})$,
+        // while uniform angle of $p$ gives $\langle p\_x\^2 / 2 m\_x E \rangle = 2 / 3$
+        let mut rng = Rng::new(7);
+        let n = 20000;
+        let mut kinetic = 0.0;
+        for _ in 0..n {
+            let q = Acoustic.final_state(m, 0, 0, energy, p, &mut || rng.uniform()).unwrap();
+            kinetic += q.x * q.x / (2.0 * 5.0 * energy) / n as f64;
+        }
+        assert!((kinetic - 0.5).abs() < 1e-2, "{}", kinetic);
+    }
+
+    /// Elastic process with constant probability, which reverses momentum
+    struct Backscattering;
+
+    impl ScatteringMechanism for Backscattering {
+        fn name(&self) -> &str {
+            "backscattering"
+        }
+        fn rate(&self, _m: &dyn MultiBand, _from: usize, _to: usize, _p: Vec2) -> f64 {
+            0.5
+        }
+        fn energy_change(&self, _m: &dyn MultiBand, _from: usize, _to: usize) -> f64 {
+            0.0
+        }
+        fn final_state(&self,
+                       _m: &dyn MultiBand,
+                       _from: usize,
+                       _to: usize,
+                       _energy: f64,
+                       p: Vec2,
+                       _uniform: &mut dyn FnMut() -> f64)
+                       -> Option<Vec2> {
+            Some(-p)
+        }
+    }
+
+    /// Elastic process with constant probability, which moves electron out of band
+    struct Lost;
+
+    impl ScatteringMechanism for Lost {
+        fn name(&self) -> &str {
+            "lost"
+        }
+        fn rate(&self, _m: &dyn MultiBand, _from: usize, _to: usize, _p: Vec2) -> f64 {
+            0.5
+        }
+        fn energy_change(&self, _m: &dyn MultiBand, _from: usize, _to: usize) -> f64 {
+            100.0
+        }
+    }
+
+    /// Backscattering, which is named as acoustic phonons
+    struct Impostor;
+
+    impl ScatteringMechanism for Impostor {
+        fn name(&self) -> &str {
+            "acoustic"
+        }
+        fn rate(&self, m: &dyn MultiBand, from: usize, to: usize, p: Vec2) -> f64 {
+            Backscattering.rate(m, from, to, p)
+        }
+        fn energy_change(&self, _m: &dyn MultiBand, _from: usize, _to: usize) -> f64 {
+            0.0
+        }
+        fn final_state(&self,
+                       m: &dyn MultiBand,
+                       from: usize,
+                       to: usize,
+                       energy: f64,
+                       p: Vec2,
+                       uniform: &mut dyn FnMut() -> f64)
+                       -> Option<Vec2> {
+            Backscattering.final_state(m, from, to, energy, p, uniform)
+        }
+    }
+
+    /// Mechanism with undefined probability
+    struct Undefined;
+
+    impl ScatteringMechanism for Undefined {
+        fn name(&self) -> &str {
+            "undefined"
+        }
+        fn rate(&self, _m: &dyn MultiBand, _from: usize, _to: usize, _p: Vec2) -> f64 {
+            f64::NAN
+        }
+        fn energy_change(&self, _m: &dyn MultiBand, _from: usize, _to: usize) -> f64 {
+            0.0
+        }
+    }
+
+    fn parabolic() -> Parabolic {
+        let bz = BrillouinZone::new(Vec2::new(-1.0, -1.0),
+                                    Vec2::new(1.0, -1.0),
+                                    Vec2::new(-1.0, 1.0));
+        Parabolic::new(10.0, bz, Phonons::new(5e-2, 0.1, 0.1))
+    }
+
+    #[test]
+    fn test_registered_mechanism() {
+        use {Fields, RunOptions, Stats, create_ensemble_seeded, run_ensemble};
+
+        let m = &with_mechanisms(parabolic(), || {
+            let mut mechanisms = phonon_mechanisms();
+            mechanisms.push(Box::new(Backscattering));
+            mechanisms
+        });
+        let f = &Fields::new((Vec2::new(0.05, 0.0), Vec2::zero(), Vec2::zero()),
+                             (0.0, 0.0, 0.0),
+                             (0.0, 0.0),
+                             0.0);
+        let ensemble = create_ensemble_seeded(20, m, 7e-3, 1);
+        let summaries = run_ensemble(&ensemble, 1e-1, 20.0, f, &RunOptions::default());
+        for s in &summaries {
+            let names: Vec<&str> = s.mechanisms.iter().map(|x| x.name.as_str()).collect();
+            assert_eq!(names,
+                       ["optical emission", "optical absorption", "acoustic", "backscattering"]);
+            assert_eq!(s.acoustic, s.mechanism("acoustic").unwrap().count);
+            assert_eq!(s.to_theta_op, s.mechanism("optical emission").unwrap().to_theta);
+            let back = s.mechanism("backscattering").unwrap();
+            assert_eq!(back.from_theta.iter().sum::<usize>(), back.count as usize);
+        }
+        let stats = Stats::from_ensemble(&summaries);
+        assert_eq!(stats.mechanisms[3].name, "backscattering");
+        // mean free time of backscattering is 2
+        assert!((stats.mechanisms[3].count - 10.0).abs() < 2.0);
+        assert_eq!(stats.mechanisms[2].count, stats.acoustic);
+        assert_eq!(stats.failed, 0.0);
+    }
+
+    #[test]
+    fn test_phonon_process() {
+        use {Fields, RunOptions, create_ensemble_seeded, run_ensemble};
+
+        assert_eq!(Acoustic.phonon_process(), Some(PhononProcess::Acoustic));
+        assert_eq!(Impostor.phonon_process(), None);
+        let m = &with_mechanisms(parabolic(), || vec![Box::new(Impostor)]);
+        let summaries = run_ensemble(&create_ensemble_seeded(10, m, 7e-3, 1),
+                                     1e-1,
+                                     20.0,
+                                     &Fields::zero(),
+                                     &RunOptions::default());
+        for s in &summaries {
+            // legacy fields are taken from acoustic phonons, not from mechanism with their name
+            assert!(s.mechanism("acoustic").unwrap().count > 0);
+            assert_eq!(s.acoustic, 0);
+            assert_eq!(s.from_theta_ac.iter().sum::<usize>(), 0);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "mechanism undefined gives invalid probability NaN")]
+    fn test_undefined_rate() {
+        let m = &with_mechanisms(parabolic(), || vec![Box::new(Acoustic), Box::new(Undefined)]);
+        max_rate(m, &m.band_mechanisms());
+    }
+
+    #[test]
+    fn test_failed_attempts() {
+        use {Fields, RunOptions, Stats, create_ensemble_seeded, run_ensemble};
+
+        let m = &with_mechanisms(parabolic(), || {
+            let mut mechanisms = phonon_mechanisms();
+            mechanisms.push(Box::new(Lost));
+            mechanisms
+        });
+        let f = &Fields::zero();
+        let ensemble = create_ensemble_seeded(20, m, 7e-3, 1);
+        let summaries = run_ensemble(&ensemble, 1e-1, 20.0, f, &RunOptions::default());
+        for s in &summaries {
+            let lost = s.mechanism("lost").unwrap();
+            assert_eq!(lost.count, 0);
+            assert_eq!(s.failed, lost.failed);
+        }
+        let stats = Stats::from_ensemble(&summaries);
+        // mean free time of lost mechanism is 2
+        assert!((stats.failed - 10.0).abs() < 2.0);
+        assert_eq!(stats.mechanisms[3].failed, stats.failed);
+        assert!(stats.acoustic > 0.0);
+    }
+
+    #[test]
+    fn test_isoenergy_branches() {
+        use test_support::MexicanHat;
+
+        let m = &MexicanHat::new();
+        // rings $p\^2 = 1 \pm 1 / 2$ have equal density of states $1 / 4 |p\^2 - 1|$
+        let p = Vec2::new(0.5f64.sqrt(), 0.0);
+        let mut rng = Rng::new(9);
+        let n = 4000;
+        let mut outer = 0;
+        for _ in 0..n {
+            let q = Acoustic.final_state(m, 0, 0, 0.25, p, &mut || rng.uniform()).unwrap();
+            assert!((m.energy(q) - 0.25).abs() < 1e-9);
+            if q.len() > 1.0 {
+                outer += 1;
+            }
+        }
+        let outer = outer as f64 / n as f64;
+        assert!((outer - 0.5).abs() < 0.03, "{}", outer);
+    }
+}
@@ -1,11 +1,119 @@
 use linal::Vec2;
-use particle::Summary;
+use particle::{Summary, MechanismSummary, Sample, Amplitude};
+
+/// Events of one scattering mechanism in ensemble
+pub struct MechanismStats {
+    pub name: String,
+    /// average number of events per particle
+    pub count: f64,
+    /// average number of events per particle, for which no final state was found
+    pub failed: f64,
+    /// histograms summed over ensemble
+    pub from_theta: Vec<usize>,
+    pub to_theta: Vec<usize>,
+    pub field_phase: Vec<usize>,
+}
+
+impl MechanismStats {
+    fn from_ensemble(ensemble: &[&MechanismSummary]) -> MechanismStats {
+        let sum = |f: &dyn Fn(&MechanismSummary) -> &Vec<usize>| {
+            ensemble.iter()
+                .map(|x| f(x).clone())
+                .reduce(|acc, x| acc.iter().zip(x.iter()).map(|(a, b)| a + b).collect())
+                .unwrap_or_default()
+        };
+        MechanismStats {
+            name: ensemble.first().map(|x| x.name.clone()).unwrap_or_default(),
+            count: ensemble.iter().map(|x| x.count).collect::<Vec<u32>>().mean(),
+            failed: ensemble.iter().map(|x| x.failed).collect::<Vec<u32>>().mean(),
+            from_theta: sum(&|x| &x.from_theta),
+            to_theta: sum(&|x| &x.to_theta),
+            field_phase: sum(&|x| &x.field_phase),
+        }
+    }
+}
+
+/// Ensemble state at one time
+pub struct SampleStats {
+    pub t: f64,
+    /// mean velocity and its standard error
+    pub current: Vec2,
+    pub current_std: Vec2,
+    /// mean energy and its standard error
+    pub energy: f64,
+    pub energy_std: f64,
+}
+
+impl SampleStats {
+    fn from_ensemble(ensemble: &[&Sample]) -> SampleStats {
+        let velocity: Vec<Vec2> = ensemble.iter().map(|x| x.velocity).collect();
+        let energy: Vec<f64> = ensemble.iter().map(|x| x.energy).collect();
+        SampleStats {
+            t: ensemble.first().map_or(0.0, |x| x.t),
+            current: velocity.mean(),
+            current_std: velocity.mean_std(),
+            energy: energy.mean(),
+            energy_std: energy.mean_std(),
+        }
+    }
+}
+
+/// Fourier component of current at one frequency
+pub struct HarmonicStats {
+    pub omega: f64,
+    /// mean complex amplitude and standard errors of its real and imaginary parts
+    pub amplitude: Amplitude,
+    pub amplitude_std: Amplitude,
+}
+
+impl HarmonicStats {
+    fn from_ensemble(ensemble: &[&(f64, Amplitude)]) -> HarmonicStats {
+        let re: Vec<Vec2> = ensemble.iter().map(|x| x.1.re).collect();
+        let im: Vec<Vec2> = ensemble.iter().map(|x| x.1.im).collect();
+        HarmonicStats {
+            omega: ensemble.first().map_or(0.0, |x| x.0),
+            amplitude: Amplitude::new(re.mean(), im.mean()),
+            amplitude_std: Amplitude::new(re.mean_std(), im.mean_std()),
+        }
+    }
+}
 
 pub struct Stats {
     pub current: Vec2,
     pub current_std: Vec2,
     pub optical: f64,
     pub acoustic: f64,
+    /// average number of optical phonon absorptions
+    pub optical_absorption: f64,
+    /// average number of interband transitions
+    pub interband: f64,
+    /// average number of intervalley transitions
+    pub intervalley: f64,
+    /// average fraction of time spent in every valley
+    pub valley_occupation: Vec<f64>,
+    /// contribution of every valley to current
+    pub valley_current: Vec<Vec2>,
+    /// events of every scattering mechanism
+    pub mechanisms: Vec<MechanismStats>,
+    /// mean displacement in real space
+    pub displacement: Vec2,
+    pub displacement_std: Vec2,
+    /// mean square displacement $\langle |\Delta r|\^2 \rangle$ at times of trajectories
+    pub msd: Vec<(f64, f64)>,
+    /// diffusion tensor $D\_{ij}$, half of growth rate of covariance of displacements
+    /// $\langle \Delta r\_i \Delta r\_j \rangle - \langle \Delta r\_i \rangle \langle \Delta r\_j \rangle$
+    /// fitted by straight line over the second half of run
+    pub diffusion: [[f64; 2]; 2],
+    /// current and energy at sampled times
+    pub samples: Vec<SampleStats>,
+    /// Fourier components of current at sampled frequencies
+    pub harmonics: Vec<HarmonicStats>,
+    /// average number of scattering attempts, for which no final state was found
+    pub failed: f64,
+    /// average number of self-scattering events
+    pub self_scattering: f64,
+    /// average number of flights drawn again with increased probability of free flight
+    pub redrawn: f64,
     pub tau: f64,
     pub energy: f64,
     pub from_theta_ac: Vec<usize>,
@@ -19,11 +127,50 @@ pub struct Stats {
 impl Stats {
     pub fn from_ensemble(ensemble: &[Summary]) -> Stats {
         let average_speed: Vec<Vec2> = ensemble.iter().map(|x| x.average_speed).collect();
+        let valleys = ensemble.iter().map(|x| x.valley_occupation.len()).min().unwrap_or(0);
+        let mechanisms = ensemble.iter().map(|x| x.mechanisms.len()).min().unwrap_or(0);
+        let displacement: Vec<Vec2> = ensemble.iter().map(|x| x.displacement).collect();
+        let points = ensemble.iter().map(|x| x.trajectory.len()).min().unwrap_or(0);
+        let trajectory = |k: usize| ensemble.iter().map(|x| x.trajectory[k].1).collect::<Vec<Vec2>>();
+        let times: Vec<f64> = (0..points).map(|k| ensemble[0].trajectory[k].0).collect();
+        let samples = ensemble.iter().map(|x| x.samples.len()).min().unwrap_or(0);
+        let harmonics = ensemble.iter().map(|x| x.harmonics.len()).min().unwrap_or(0);
         Stats {
             current: average_speed.mean(),
             current_std: average_speed.mean_std(),
             optical: ensemble.iter().map(|x| x.optical).collect::<Vec<u32>>().mean(),
             acoustic: ensemble.iter().map(|x| x.acoustic).collect::<Vec<u32>>().mean(),
+            optical_absorption: ensemble.iter().map(|x| x.optical_absorption).collect::<Vec<u32>>().mean(),
+            interband: ensemble.iter().map(|x| x.interband).collect::<Vec<u32>>().mean(),
+            intervalley: ensemble.iter().map(|x| x.intervalley).collect::<Vec<u32>>().mean(),
+            valley_occupation: (0..valleys)
+                .map(|i| ensemble.iter().map(|x| x.valley_occupation[i]).collect::<Vec<f64>>().mean())
+                .collect(),
+            valley_current: (0..valleys)
+                .map(|i| ensemble.iter().map(|x| x.valley_current[i]).collect::<Vec<Vec2>>().mean())
+                .collect(),
+            mechanisms: (0..mechanisms)
+                .map(|i| {
+                    MechanismStats::from_ensemble(&ensemble.iter()
+                        .map(|x| &x.mechanisms[i])
+                        .collect::<Vec<_>>())
+                })
+                .collect(),
+            displacement: displacement.mean(),
+            displacement_std: displacement.mean_std(),
+            msd: (0..points)
+                .map(|k| (times[k], trajectory(k).iter().map(|r| r.dot(*r)).collect::<Vec<f64>>().mean()))
+                .collect(),
+            diffusion: diffusion(&times, &(0..points).map(|k| covariance(&trajectory(k))).collect::<Vec<_>>()),
+            samples: (0..samples)
+                .map(|k| SampleStats::from_ensemble(&ensemble.iter().map(|x| &x.samples[k]).collect::<Vec<_>>()))
+                .collect(),
+            harmonics: (0..harmonics)
+                .map(|k| HarmonicStats::from_ensemble(&ensemble.iter().map(|x| &x.harmonics[k]).collect::<Vec<_>>()))
+                .collect(),
+            failed: ensemble.iter().map(|x| x.failed).collect::<Vec<u32>>().mean(),
+            self_scattering: ensemble.iter().map(|x| x.self_scattering).collect::<Vec<u32>>().mean(),
+            redrawn: ensemble.iter().map(|x| x.redrawn).collect::<Vec<u32>>().mean(),
             tau: ensemble.iter().map(|x| x.tau).collect::<Vec<f64>>().mean(),
             energy: ensemble.iter().map(|x| x.energy).collect::<Vec<f64>>().mean(),
             from_theta_ac: ensemble.iter().map(|x| x.from_theta_ac.as_slice().to_vec()).reduce(|acc, x| acc.iter().zip(x.iter()).map(|(a, b)| a + b).collect::<Vec<usize>>()).unwrap(),
@@ -36,6 +183,162 @@ impl Stats {
     }
 }
 
+/// Covariance matrix of points
+fn covariance(points: &[Vec2]) -> [[f64; 2]; 2] {
+    let mean = points.mean();
+    let mut c = [[0.0; 2]; 2];
+    for &r in points {
+        let d = [r.x - mean.x, r.y - mean.y];
+        for i in 0..2 {
+            for j in 0..2 {
+                c[i][j] += d[i] * d[j] / points.len() as f64;
+            }
+        }
+    }
+    c
+}
+
+/// Half of slope of covariance `c` over `times` in the second half of run by least squares
+fn diffusion(times: &[f64], c: &[[[f64; 2]; 2]]) -> [[f64; 2]; 2] {
+    let mut d = [[0.0; 2]; 2];
+    let end = match times.last() {
+        Some(&end) => end,
+        None => return d,
+    };
+    let k: Vec<usize> = (0..times.len()).filter(|&k| times[k] >= end / 2.0).collect();
+    if k.len() < 2 {
+        return d;
+    }
+    let t_mean = k.iter().map(|&k| times[k]).sum::<f64>() / k.len() as f64;
+    let t_var: f64 = k.iter().map(|&k| (times[k] - t_mean).powi(2)).sum();
+    for i in 0..2 {
+        for j in 0..2 {
+            let c_mean = k.iter().map(|&k| c[k][i][j]).sum::<f64>() / k.len() as f64;
+            let cov: f64 = k.iter().map(|&k| (times[k] - t_mean) * (c[k][i][j] - c_mean)).sum();
+            d[i][j] = cov / t_var / 2.0;
+        }
+    }
+    d
+}
+
+#[test]
+fn test_diffusion() {
+    use material::BrillouinZone;
+    use materials::{Parabolic, Phonons};
+    use {Fields, RunOptions, create_ensemble_seeded, run_ensemble};
+
+    // elastic isotropic scattering with probability $1 / \tau = 0.5$ gives $D = T \tau / m$
+    let bz = BrillouinZone::new(Vec2::new(-1.0, -1.0),
+                                Vec2::new(1.0, -1.0),
+                                Vec2::new(-1.0, 1.0));
+    let m = &Parabolic::new(10.0, bz, Phonons::new(1.0, 0.05, 0.1));
+    let f = &Fields::zero();
+    let temperature = 7e-3;
+    let ensemble = create_ensemble_seeded(2000, m, temperature, 5);
+    let summaries = run_ensemble(&ensemble, 10.0, 100.0, f, &RunOptions::default());
+    for s in &summaries {
+        assert_eq!(s.trajectory.len(), 64);
+        assert_eq!(s.trajectory[63], (100.0, s.displacement));
+        assert!((s.displacement - s.average_speed * 100.0).len() < 1e-9);
+    }
+    let stats = Stats::from_ensemble(&summaries);
+    let expected = temperature * 2.0 / 10.0;
+    assert!((stats.diffusion[0][0] - expected).abs() < 0.2 * expected, "{:?}", stats.diffusion);
+    assert!((stats.diffusion[1][1] - expected).abs() < 0.2 * expected, "{:?}", stats.diffusion);
+    assert!(stats.diffusion[0][1].abs() < 0.2 * expected);
+    assert_eq!(stats.diffusion[0][1], stats.diffusion[1][0]);
+    // mean square displacement grows
+    assert!(stats.msd[63].1 > stats.msd[31].1 && stats.msd[31].1 > stats.msd[0].1);
+    assert!(stats.displacement.len() < 3.0 * stats.displacement_std.len());
+}
+
+#[test]
+fn test_transient_current() {
+    use material::BrillouinZone;
+    use materials::{Parabolic, Phonons};
+    use particle::Sampling;
+    use {Fields, RunOptions, create_ensemble_seeded, run_ensemble};
+
+    // drift velocity relaxes as $-E \tau (1 - e\^{-t / \tau}) / m$ with $\tau = 2$
+    let bz = BrillouinZone::new(Vec2::new(-1.0, -1.0),
+                                Vec2::new(1.0, -1.0),
+                                Vec2::new(-1.0, 1.0));
+    let m = &Parabolic::new(10.0, bz, Phonons::new(1.0, 0.05, 0.1));
+    let f = &Fields::new((Vec2::new(0.05, 0.0), Vec2::zero(), Vec2::zero()),
+                         (0.0, 0.0, 0.0),
+                         (0.0, 0.0),
+                         0.0);
+    let times: Vec<f64> = (0..6).map(|i| i as f64 * 2.0).collect();
+    let ensemble = create_ensemble_seeded(1000, m, 7e-3, 5);
+    let sampling = Sampling::new(times.clone(), vec![]);
+    let options = &RunOptions { sampling, ..RunOptions::default() };
+    let summaries = run_ensemble(&ensemble, 1e-1, 11.0, f, options);
+    let stats = Stats::from_ensemble(&summaries);
+    assert_eq!(stats.samples.len(), 6);
+    for (s, &t) in stats.samples.iter().zip(&times) {
+        let expected = -0.01 * (1.0 - (-t / 2.0).exp());
+        assert_eq!(s.t, t);
+        assert!((s.current.x - expected).abs() < 4.0 * s.current_std.x,
+                "{}: {} != {} ± {}", t, s.current.x, expected, s.current_std.x);
+        assert!(s.energy_std > 0.0);
+    }
+    // electrons are heated by field
+    assert!(stats.samples[5].energy > stats.samples[0].energy);
+    // samples after the end of run are skipped
+    let summaries = run_ensemble(&ensemble[..2], 1e-1, 3.0, f, options);
+    assert_eq!(summaries[0].samples.len(), 2);
+}
+
+#[test]
+fn test_harmonics() {
+    use material::BrillouinZone;
+    use materials::{Parabolic, Phonons, Superlattice1D};
+    use particle::Sampling;
+    use {FieldSource, Fields, RunOptions, create_ensemble_seeded, run_ensemble};
+    use std::f64::consts::PI;
+
+    // without scattering $p = p\_0 - E \sin(\omega t) / \omega$
+    let bz = BrillouinZone::new(Vec2::new(-1.0, -1.0),
+                                Vec2::new(1.0, -1.0),
+                                Vec2::new(-1.0, 1.0));
+    let m = &Parabolic::new(10.0, bz, Phonons::new(0.0, 0.0, 0.0));
+    let f = &Fields::new((Vec2::zero(), Vec2::new(0.02, 0.0), Vec2::zero()),
+                         (0.0, 0.0, 0.0),
+                         (1.0, 3.0),
+                         0.0);
+    let frequencies = vec![0.0, f.combination(1, 0), f.combination(2, 0), f.combination(-2, 1)];
+    let sampling = Sampling::new(vec![], frequencies);
+    let options = &RunOptions { sampling, ..RunOptions::default() };
+    let ensemble = create_ensemble_seeded(20, m, 7e-3, 1);
+    let summaries = run_ensemble(&ensemble, 1e-2, 20.0 * PI, f, options);
+    for (s, x) in summaries.iter().zip(&ensemble) {
+        assert!((s.harmonics[0].1.re - x.init_condition / 10.0).len() < 1e-6);
+        assert!((s.harmonics[0].1.re - s.average_speed).len() < 1e-6);
+    }
+    let stats = Stats::from_ensemble(&summaries);
+    assert_eq!(stats.harmonics.len(), 4);
+    assert_eq!(stats.harmonics[3].omega, 1.0);
+    let first = &stats.harmonics[1];
+    assert!(first.amplitude.re.len() < 1e-6);
+    assert!((first.amplitude.im - Vec2::new(2e-3, 0.0)).len() < 1e-6);
+    assert!(first.amplitude_std.im.len() < 1e-9);
+    // parabolic band gives no second harmonic, superlattice does
+    assert!(stats.harmonics[2].amplitude.re.len() < 1e-6);
+    assert!(stats.harmonics[2].amplitude.im.len() < 1e-6);
+
+    let m = &Superlattice1D::new(1.0, 1.0, 1.0, 6.0, Phonons::new(0.0, 0.0, 0.0));
+    // Bloch frequency equal to the wave one mixes them into harmonics
+    let f = &Fields::new((Vec2::new(1.0, 0.0), Vec2::new(1.0, 0.0), Vec2::zero()),
+                         (0.0, 0.0, 0.0),
+                         (1.0, 0.0),
+                         0.0);
+    let ensemble = create_ensemble_seeded(20, m, 0.3, 1);
+    let summaries = run_ensemble(&ensemble, 1e-2, 20.0 * PI, f, options);
+    let stats = Stats::from_ensemble(&summaries);
+    let second = &stats.harmonics[2];
+    assert!((second.amplitude.re.len() + second.amplitude.im.len()) > 1e-2);
+}
+
 pub struct Histogram {
     pub min: f64,
     pub max: f64,
@@ -45,6 +348,7 @@ pub struct Histogram {
 }
 
 impl Histogram {
+    #[allow(clippy::redundant_field_names)]
     pub fn new(min: f64, max: f64, n_bins: usize) -> Histogram {
         Histogram {
             min: min,
@@ -79,6 +383,7 @@ impl Mean for [Vec2] {
 impl Mean for [u32] {
     type Output = f64;
 
+    #[allow(clippy::unnecessary_fold)]
     fn mean(&self) -> Self::Output {
         let sum = self.iter().fold(0, |acc, &x| acc + x);
         sum as f64 / self.len() as f64
@@ -96,6 +401,7 @@ impl Mean for [f64] {
 
 
 #[test]
+#[allow(clippy::useless_vec)]
 fn test_mean() {
     let data_f64 = vec![-2.0, -1.0, 0.0, 1.0, 2.0];
     assert_eq!(data_f64.mean(), 0.0);
@@ -133,6 +439,7 @@ impl MeanStd for [f64] {
 }
 
 #[test]
+#[allow(clippy::useless_vec)]
 fn test_mean_std() {
     let data_vec2 = vec![Vec2::new(1.0, 2.0), Vec2::new(-3.0, 4.0)];
     assert!((data_vec2.mean_std() - Vec2::new(2.0, 1.0) / f64::sqrt(2.0)).len() < 1e-10);
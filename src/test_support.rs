@@ -0,0 +1,97 @@
+//! Materials shared by tests of several modules
+use material::{Material, BrillouinZone};
+use scattering::ScatteringMechanism;
+use linal::Vec2;
+
+/// Material with "mexican hat" spectrum $E(p) = (p\^2 - 1)\^2$, which defines only energy
+pub struct MexicanHat {
+    pub bz: BrillouinZone,
+}
+
+impl MexicanHat {
+    /// Mexican hat in square zone $[-2, 2]\^2$
+    pub fn new() -> MexicanHat {
+        MexicanHat {
+            bz: BrillouinZone::new(Vec2::new(-2.0, -2.0), Vec2::new(2.0, -2.0), Vec2::new(-2.0, 2.0)),
+        }
+    }
+}
+
+impl Material for MexicanHat {
+    fn energy(&self, p: Vec2) -> f64 {
+        (p.dot(p) - 1.0).powi(2)
+    }
+    fn min_energy(&self) -> f64 {
+        0.0
+    }
+    fn max_energy(&self) -> f64 {
+        49.0
+    }
+    fn brillouin_zone(&self) -> &BrillouinZone {
+        &self.bz
+    }
+    fn optical_energy(&self) -> f64 {
+        0.0
+    }
+    fn optical_scattering(&self, _p: Vec2) -> f64 {
+        0.0
+    }
+    fn acoustic_scattering(&self, _p: Vec2) -> f64 {
+        0.0
+    }
+}
+
+/// Material `m`, in which electron is scattered by `mechanisms()` instead of its own mechanisms
+pub struct WithMechanisms<T: Material> {
+    pub m: T,
+    pub mechanisms: Box<dyn Fn() -> Vec<Box<dyn ScatteringMechanism>> + Sync>,
+}
+
+pub fn with_mechanisms<T, F>(m: T, mechanisms: F) -> WithMechanisms<T>
+    where T: Material,
+          F: Fn() -> Vec<Box<dyn ScatteringMechanism>> + Sync + 'static
+{
+    WithMechanisms {
+        m,
+        mechanisms: Box::new(mechanisms),
+    }
+}
+
+impl<T: Material> Material for WithMechanisms<T> {
+    fn energy(&self, p: Vec2) -> f64 {
+        self.m.energy(p)
+    }
+    fn energy_gradient(&self, p: Vec2) -> Vec2 {
+        self.m.energy_gradient(p)
+    }
+    fn velocity(&self, p: Vec2) -> Vec2 {
+        self.m.velocity(p)
+    }
+    fn min_energy(&self) -> f64 {
+        self.m.min_energy()
+    }
+    fn max_energy(&self) -> f64 {
+        self.m.max_energy()
+    }
+    fn momentums(&self, energy: f64, theta: f64) -> Vec<Vec2> {
+        self.m.momentums(energy, theta)
+    }
+    fn brillouin_zone(&self) -> &BrillouinZone {
+        self.m.brillouin_zone()
+    }
+    fn optical_energy(&self) -> f64 {
+        self.m.optical_energy()
+    }
+    fn optical_scattering(&self, p: Vec2) -> f64 {
+        self.m.optical_scattering(p)
+    }
+    fn optical_absorption(&self, p: Vec2) -> f64 {
+        self.m.optical_absorption(p)
+    }
+    fn acoustic_scattering(&self, p: Vec2) -> f64 {
+        self.m.acoustic_scattering(p)
+    }
+    fn mechanisms(&self) -> Vec<Box<dyn ScatteringMechanism>> {
+        (self.mechanisms)()
+    }
+}
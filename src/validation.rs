@@ -0,0 +1,231 @@
+//! Provides consistency checks of `Material` implementations
+use std::f64::consts::PI;
+use material::Material;
+use linal::Vec2;
+
+/// Step of numerical differentiation relative to size of brillouin zone
+const STEP: f64 = 1e-6;
+
+#[derive(Clone, Debug, PartialEq)]
+/// Inconsistency found in material
+pub enum Violation {
+    /// `energy_gradient` differs from finite differences of `energy`
+    Gradient { p: Vec2, gradient: Vec2, numeric: Vec2 },
+    /// `velocity` differs from finite differences of `energy`
+    Velocity { p: Vec2, velocity: Vec2, numeric: Vec2 },
+    /// point returned by `momentums` does not lie on isoenergy line
+    OffShell { energy: f64, theta: f64, p: Vec2, actual: f64 },
+    /// point returned by `momentums` lies outside of first brillouin zone or in other direction
+    OutsideRay { energy: f64, theta: f64, p: Vec2 },
+    /// energy in brillouin zone is out of [`min_energy`, `max_energy`]
+    OutOfBounds { p: Vec2, energy: f64 },
+    /// `min_energy` or `max_energy` is not reached in brillouin zone
+    LooseBounds { declared: (f64, f64), sampled: (f64, f64) },
+    /// energy is discontinuous across boundary of brillouin zone, i.e. is not periodic
+    Periodicity { p: Vec2, folded: Vec2, energy: f64, folded_energy: f64 },
+}
+
+/// Result of material check
+pub struct Report {
+    /// number of checked points of brillouin zone
+    pub samples: usize,
+    pub violations: Vec<Violation>,
+}
+
+impl Report {
+    pub fn is_ok(&self) -> bool {
+        self.violations.is_empty()
+    }
+}
+
+/// Checks material on $n \times n$ grid in brillouin zone, $n$ isoenergy levels and
+/// $4 n$ directions. Differences greater than `tolerance`, relative to bandwidth
+/// (or typical gradient), are reported.
+pub fn check<T: Material>(m: &T, n: usize, tolerance: f64) -> Report {
+    let bz = m.brillouin_zone();
+    let (b1, b2) = bz.basis();
+    let scale = b1.len() + b2.len();
+    let h = STEP * scale;
+    let (min, max) = (m.min_energy(), m.max_energy());
+    let width = max - min;
+    let gradient_scale = width / scale;
+
+    let numeric_gradient = |p: Vec2| {
+        let dx = Vec2::new(h, 0.0);
+        let dy = Vec2::new(0.0, h);
+        Vec2::new(m.energy(p + dx) - m.energy(p - dx),
+                  m.energy(p + dy) - m.energy(p - dy)) / (2.0 * h)
+    };
+
+    let mut violations = Vec::new();
+    let mut sampled = (f64::INFINITY, f64::NEG_INFINITY);
+
+    let mut points: Vec<Vec2> = bz.vertices().to_vec();
+    for i in 0..n {
+        for j in 0..n {
+            points.push(bz.sample((i as f64 + 0.5) / n as f64, (j as f64 + 0.5) / n as f64));
+        }
+    }
+
+    for &p in &points {
+        let e = m.energy(p);
+        sampled = (sampled.0.min(e), sampled.1.max(e));
+        if e < min - tolerance * width || e > max + tolerance * width {
+            violations.push(Violation::OutOfBounds { p, energy: e });
+        }
+
+        let numeric = numeric_gradient(p);
+        let bound = tolerance * numeric.len().max(gradient_scale);
+        let gradient = m.energy_gradient(p);
+        if (gradient - numeric).len() > bound {
+            violations.push(Violation::Gradient { p, gradient, numeric });
+        }
+        let velocity = m.velocity(p);
+        if (velocity - numeric).len() > bound {
+            violations.push(Violation::Velocity { p, velocity, numeric });
+        }
+    }
+
+    // extrema are reached inside grid cells, so only difference greater than
+    // variation of energy on one cell is reported
+    let cell = gradient_scale * scale / n as f64;
+    if sampled.0 - min > tolerance * width + cell || max - sampled.1 > tolerance * width + cell {
+        violations.push(Violation::LooseBounds {
+            declared: (min, max),
+            sampled,
+        });
+    }
+
+    for k in 0..n {
+        let energy = min + width * (k as f64 + 0.5) / n as f64;
+        for i in 0..4 * n {
+            let theta = 2.0 * PI * i as f64 / (4 * n) as f64;
+            let l = Vec2::from_polar(1.0, theta);
+            for p in m.momentums(energy, theta) {
+                let actual = m.energy(p);
+                if (actual - energy).abs() > tolerance * width {
+                    violations.push(Violation::OffShell { energy, theta, p, actual });
+                }
+                let along = p.dot(l);
+                if along < 0.0 || p.area(l).abs() > tolerance * scale ||
+                   along > bz.pmax(theta) * (1.0 + tolerance) {
+                    violations.push(Violation::OutsideRay { energy, theta, p });
+                }
+            }
+        }
+    }
+
+    // every point of boundary is compared with its image behind the opposite edge
+    let vs = bz.vertices();
+    for i in 0..vs.len() {
+        let (v1, v2) = (vs[i], vs[(i + 1) % vs.len()]);
+        let outward = (v2 - v1).cross().ort();
+        for j in 0..n {
+            let p = v1 + (v2 - v1) * ((j as f64 + 0.5) / n as f64);
+            let folded = bz.to_first_bz(p + outward * h);
+            let energy = m.energy(p);
+            let folded_energy = m.energy(folded);
+            let bound = tolerance * width + numeric_gradient(p).len() * h * 2.0;
+            if (energy - folded_energy).abs() > bound {
+                violations.push(Violation::Periodicity { p, folded, energy, folded_energy });
+            }
+        }
+    }
+
+    Report {
+        samples: points.len(),
+        violations,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use material::BrillouinZone;
+    use materials::{Parabolic, Phonons, Superlattice2D};
+
+    fn square() -> BrillouinZone {
+        BrillouinZone::new(Vec2::new(-1.0, -1.0),
+                           Vec2::new(1.0, -1.0),
+                           Vec2::new(-1.0, 1.0))
+    }
+
+    /// Parabolic band with deliberate mistakes
+    struct Broken {
+        m: Parabolic,
+        bz: BrillouinZone,
+        shift: bool,
+    }
+
+    impl Material for Broken {
+        fn energy(&self, p: Vec2) -> f64 {
+            let e = self.m.energy(p);
+            if self.shift { e + 0.01 * p.x } else { e }
+        }
+        fn energy_gradient(&self, p: Vec2) -> Vec2 {
+            self.m.energy_gradient(p)
+        }
+        fn velocity(&self, p: Vec2) -> Vec2 {
+            self.m.velocity(p) * 2.0
+        }
+        fn min_energy(&self) -> f64 {
+            0.0
+        }
+        fn max_energy(&self) -> f64 {
+            0.2
+        }
+        fn momentums(&self, energy: f64, theta: f64) -> Vec<Vec2> {
+            self.m.momentums(energy * 1.1, theta)
+        }
+        fn brillouin_zone(&self) -> &BrillouinZone {
+            &self.bz
+        }
+        fn optical_energy(&self) -> f64 {
+            0.0
+        }
+        fn optical_scattering(&self, _p: Vec2) -> f64 {
+            0.0
+        }
+        fn acoustic_scattering(&self, _p: Vec2) -> f64 {
+            0.0
+        }
+    }
+
+    #[test]
+    fn test_check_valid() {
+        let m = Parabolic::new(10.0, square(), Phonons::new(0.05, 0.1, 0.1));
+        let report = check(&m, 20, 1e-4);
+        assert!(report.is_ok(), "{:?}", report.violations);
+        assert_eq!(report.samples, 404);
+
+        let m = Superlattice2D::new((1.0, 0.5), (1.0, 2.0), Phonons::new(0.2, 0.1, 0.1));
+        assert!(check(&m, 20, 1e-4).is_ok());
+    }
+
+    #[test]
+    fn test_check_broken() {
+        let m = Broken {
+            m: Parabolic::new(10.0, square(), Phonons::new(0.05, 0.1, 0.1)),
+            bz: square(),
+            shift: false,
+        };
+        let report = check(&m, 20, 1e-4);
+        let has = |f: &dyn Fn(&Violation) -> bool| report.violations.iter().any(f);
+        assert!(has(&|v| matches!(*v, Violation::Velocity { .. })));
+        assert!(has(&|v| matches!(*v, Violation::OffShell { .. })));
+        assert!(has(&|v| matches!(*v, Violation::LooseBounds { .. })));
+        assert!(!has(&|v| matches!(*v, Violation::Gradient { .. })));
+        assert!(!has(&|v| matches!(*v, Violation::Periodicity { .. })));
+
+        let m = Broken {
+            m: Parabolic::new(10.0, square(), Phonons::new(0.05, 0.1, 0.1)),
+            bz: square(),
+            shift: true,
+        };
+        let report = check(&m, 20, 1e-4);
+        let has = |f: &dyn Fn(&Violation) -> bool| report.violations.iter().any(f);
+        assert!(has(&|v| matches!(*v, Violation::Gradient { .. })));
+        assert!(has(&|v| matches!(*v, Violation::Periodicity { .. })));
+        assert!(has(&|v| matches!(*v, Violation::OutOfBounds { .. })));
+    }
+}